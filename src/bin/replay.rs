@@ -0,0 +1,17 @@
+use anyhow::{anyhow, Result};
+use gharry::{config::Config, env, replay};
+use std::path::Path;
+
+fn main() -> Result<()> {
+    let setup = env::setup_exec_env()?;
+    let config = Config::build_default(setup.work_dir)?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let path = match args.first() {
+        Some(path) => Path::new(path),
+        None => return Err(anyhow!("usage: replay <maildir or mbox path> [notify]")),
+    };
+    let send_notifs = args.get(1).map(|v| v == "notify").unwrap_or(false);
+    replay::run(&config, path, send_notifs)?;
+
+    Ok(())
+}
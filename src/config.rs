@@ -1,4 +1,10 @@
+use crate::{
+    digest, github,
+    notif::NotifKind,
+    rules::{self, Glob, RuleSet},
+};
 use anyhow::{anyhow, Context, Result};
+use regex::Regex;
 use serde::Deserialize;
 use std::{
     fs,
@@ -7,15 +13,113 @@ use std::{
 
 #[derive(Debug, Deserialize)]
 struct RawConfig {
+    pub name: Option<String>,
     pub slack_oauth_bot_token: String,
-    pub slack_mail_channel_id: String,
+    pub slack_mail_channel_id: Option<String>,
     pub slack_dest_channel_id: String,
     pub github_access_token: String,
     pub github_login_name: String,
+    pub github_web_base: Option<String>,
+    pub github_extra_web_bases: Option<Vec<String>>,
+    pub github_api_base: Option<String>,
+    pub github_graphql_url: Option<String>,
+    pub slack_api_base: Option<String>,
+    pub slack_thread_replies: Option<bool>,
+    pub slack_reply_broadcast: Option<bool>,
+    pub slack_message_format: Option<MessageFormat>,
+    pub slack_catch_up_limit: Option<usize>,
+    pub imap: Option<RawImapConfig>,
+    #[serde(default)]
+    pub sources: Vec<RawSource>,
+    pub sinks: Option<Vec<SinkConfig>>,
+    #[serde(default)]
+    pub routes: Vec<RawRoute>,
+    #[serde(default)]
+    pub rules: Vec<RawRule>,
+    pub digest: Option<RawDigest>,
+    pub interactivity: Option<RawInteractivity>,
+    pub webhook: Option<RawWebhook>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RawSource {
+    Slack { channel_id: String },
+    Imap(RawImapConfig),
+    Notifications,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawImapConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub tls: Option<bool>,
+    pub username: String,
+    pub password: String,
+    pub folder: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRoute {
+    pub repo: String,
+    pub channel: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRule {
+    pub kind: Option<NotifKind>,
+    pub repo: Option<String>,
+    pub actor: Option<String>,
+    pub team: Option<String>,
+    pub review_state: Option<github::ReviewState>,
+    pub comment: Option<String>,
+    pub reason: Option<github::NotificationReason>,
+    pub action: RawAction,
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawAction {
+    Alert,
+    Silence,
+    Drop,
+    Unsubscribe,
+    Digest,
+    Route,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawDigest {
+    pub schedule: RawSchedule,
+    pub hour: Option<u64>,
+    pub kinds: Option<Vec<NotifKind>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawInteractivity {
+    pub listen: String,
+    pub signing_secret: String,
+    pub snooze_hours: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawWebhook {
+    pub listen: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawSchedule {
+    Hourly,
+    Daily,
 }
 
 impl RawConfig {
-    pub fn from_file(work_dir: &Path) -> Result<Self> {
+    // Read configs for each profile. The top-level keys are shared by all profiles
+    // and each profile can override them. Without `[[profiles]]`, there is only one profile.
+    pub fn from_file(work_dir: &Path) -> Result<Vec<Self>> {
         let config_path = {
             let mut path = work_dir.to_path_buf();
             path.push("config.toml");
@@ -25,44 +129,459 @@ impl RawConfig {
             return Err(anyhow!("config file not found: {}", config_path.display()));
         }
         let file_content = fs::read_to_string(&config_path)?;
-        toml::from_str(&file_content).with_context(|| "failed to parse config file")
+        RawConfig::parse_profiles(&file_content)
+    }
+
+    fn parse_profiles(content: &str) -> Result<Vec<Self>> {
+        let mut base = match toml::from_str(content) {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => return Err(anyhow!("config file must be a table")),
+            Err(err) => return Err(err).context("failed to parse config file"),
+        };
+
+        let profiles = match base.remove("profiles") {
+            None => vec![toml::value::Table::new()],
+            Some(toml::Value::Array(profiles)) => profiles
+                .into_iter()
+                .map(|p| match p {
+                    toml::Value::Table(table) => Ok(table),
+                    _ => Err(anyhow!("each profile must be a table")),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Some(_) => return Err(anyhow!("profiles must be an array of tables")),
+        };
+
+        profiles
+            .into_iter()
+            .enumerate()
+            .map(|(idx, profile)| {
+                let mut table = base.clone();
+                table.extend(profile);
+                toml::Value::Table(table)
+                    .try_into()
+                    .with_context(|| format!("failed to parse config of profile #{}", idx))
+            })
+            .collect()
+    }
+}
+
+impl RawImapConfig {
+    fn build(self) -> ImapConfig {
+        let tls = self.tls.unwrap_or(true);
+        ImapConfig {
+            host: self.host,
+            port: self.port.unwrap_or(if tls { 993 } else { 143 }),
+            tls,
+            username: self.username,
+            password: self.password,
+            folder: self.folder.unwrap_or_else(|| String::from("INBOX")),
+        }
+    }
+}
+
+impl RawDigest {
+    fn build(self) -> Result<DigestConfig> {
+        let schedule = match self.schedule {
+            RawSchedule::Hourly => digest::Schedule::Hourly,
+            RawSchedule::Daily => match self.hour.unwrap_or(0) {
+                hour if hour < 24 => digest::Schedule::Daily { hour },
+                hour => return Err(anyhow!("invalid digest hour: {}", hour)),
+            },
+        };
+        // By default, the notifications that are not alerted are digested.
+        let kinds = self.kinds.unwrap_or_else(|| {
+            vec![
+                NotifKind::Pushed,
+                NotifKind::PrOpened,
+                NotifKind::IssueClosed,
+                NotifKind::WorkflowCancelled,
+            ]
+        });
+        Ok(DigestConfig { schedule, kinds })
+    }
+}
+
+impl RawRule {
+    fn build(self) -> Result<rules::Rule> {
+        let action = match self.action {
+            RawAction::Alert => rules::Action::Alert,
+            RawAction::Silence => rules::Action::Silence,
+            RawAction::Drop => rules::Action::Drop,
+            RawAction::Unsubscribe => rules::Action::Unsubscribe,
+            RawAction::Digest => rules::Action::Digest,
+            RawAction::Route => match self.channel {
+                Some(channel) => rules::Action::Route { channel },
+                None => return Err(anyhow!("route rule requires channel")),
+            },
+        };
+        let matcher = rules::Matcher {
+            kind: self.kind,
+            repo: self.repo.as_deref().map(Glob::new).transpose()?,
+            actor: self.actor.as_deref().map(Glob::new).transpose()?,
+            team: self.team.as_deref().map(Glob::new).transpose()?,
+            review_state: self.review_state,
+            comment: self
+                .comment
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("invalid comment regex in rule")?,
+            reason: self.reason,
+        };
+        Ok(rules::Rule { matcher, action })
     }
 }
 
 #[derive(Debug)]
 pub struct Config {
+    pub profile: String,
     pub dir: PathBuf,
     pub slack: SlackConfig,
     pub github: GitHubConfig,
+    pub sources: Vec<SourceConfig>,
+    pub sinks: Vec<SinkConfig>,
+    pub rules: RuleSet,
+    pub digest: Option<DigestConfig>,
+    pub interactivity: Option<InteractivityConfig>,
+    pub webhook: Option<WebhookConfig>,
 }
 
 impl Config {
+    pub fn build_profiles(work_dir: PathBuf) -> Result<Vec<Self>> {
+        let configs = RawConfig::from_file(&work_dir)?
+            .into_iter()
+            .map(|raw| Config::build(work_dir.clone(), raw))
+            .collect::<Result<Vec<_>>>()?;
+        for (idx, config) in configs.iter().enumerate() {
+            if configs[..idx].iter().any(|c| c.profile == config.profile) {
+                return Err(anyhow!("duplicate profile: {}", config.profile));
+            }
+        }
+        Ok(configs)
+    }
+
+    // The path of a state file of the profile. Each profile has its own directory so that
+    // profiles never share a state even if they read the same source.
+    pub fn profile_path(&self, name: &str) -> Result<PathBuf> {
+        let dir = self.dir.join("profiles").join(&self.profile);
+        fs::create_dir_all(&dir).context("failed to create profile directory")?;
+        Ok(dir.join(name))
+    }
+
+    // Same as `profile_path`, but a file of older versions in the config directory is moved there.
+    pub fn state_path(&self, name: &str, legacy_name: &str) -> Result<PathBuf> {
+        let path = self.profile_path(name)?;
+        let legacy_path = self.dir.join(legacy_name);
+        if !path.exists() && legacy_path.exists() {
+            log::info!("Move {} to {}", legacy_path.display(), path.display());
+            fs::rename(&legacy_path, &path).context("failed to move old state file")?;
+        }
+        Ok(path)
+    }
+
+    // Build a config of the profile specified by `GHARRY_PROFILE` or the first profile.
     pub fn build_default(work_dir: PathBuf) -> Result<Self> {
-        let raw = RawConfig::from_file(&work_dir)?;
+        let profile = std::env::var("GHARRY_PROFILE").ok();
+        let mut configs = Config::build_profiles(work_dir)?;
+        match profile {
+            None => Ok(configs.remove(0)),
+            Some(name) => match configs.into_iter().find(|c| c.profile == name) {
+                Some(config) => Ok(config),
+                None => Err(anyhow!("profile not found: {}", name)),
+            },
+        }
+    }
+
+    fn build(work_dir: PathBuf, raw: RawConfig) -> Result<Self> {
+        let profile = match raw.name {
+            Some(name) => name,
+            None => raw.github_login_name.clone(),
+        };
+
+        // `slack_mail_channel_id` and `[imap]` are shorthands of `[[sources]]`.
+        let mut sources = Vec::new();
+        if let Some(channel_id) = raw.slack_mail_channel_id {
+            sources.push(SourceConfig::SlackChannel { channel_id });
+        }
+        if let Some(imap) = raw.imap {
+            sources.push(SourceConfig::Imap(imap.build()));
+        }
+        sources.extend(raw.sources.into_iter().map(|s| match s {
+            RawSource::Slack { channel_id } => SourceConfig::SlackChannel { channel_id },
+            RawSource::Imap(imap) => SourceConfig::Imap(imap.build()),
+            RawSource::Notifications => SourceConfig::GitHubNotifications,
+        }));
+        // A profile can receive notifications only by webhooks.
+        if sources.is_empty() && raw.webhook.is_none() {
+            return Err(anyhow!("no notification sources configured: {}", profile));
+        }
+
+        let routes = raw
+            .routes
+            .into_iter()
+            .map(|r| {
+                Ok(Route {
+                    repo: Glob::new(&r.repo)?,
+                    channel: r.channel,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let rules = raw
+            .rules
+            .into_iter()
+            .map(RawRule::build)
+            .collect::<Result<Vec<_>>>()?;
+
+        let digest = raw.digest.map(RawDigest::build).transpose()?;
+        if digest.is_none() && rules.iter().any(|r| r.action == rules::Action::Digest) {
+            return Err(anyhow!("digest rule requires [digest] config: {}", profile));
+        }
+        let digest_kinds = digest.as_ref().map_or(&[][..], |d| &d.kinds);
+
         Ok(Config {
+            profile,
             dir: work_dir,
             slack: SlackConfig {
                 bot_token: raw.slack_oauth_bot_token,
-                mail_channel_id: raw.slack_mail_channel_id,
+                api_base: raw
+                    .slack_api_base
+                    .unwrap_or_else(|| String::from("https://slack.com/api")),
                 dest_channel_id: raw.slack_dest_channel_id,
+                routes,
+                thread_replies: raw.slack_thread_replies.unwrap_or(false),
+                reply_broadcast: raw.slack_reply_broadcast.unwrap_or(false),
+                message_format: raw.slack_message_format.unwrap_or(MessageFormat::Text),
+                catch_up_limit: raw.slack_catch_up_limit.unwrap_or(DEFAULT_CATCH_UP_LIMIT),
             },
             github: GitHubConfig {
                 auth_token: raw.github_access_token,
                 login_name: raw.github_login_name,
+                endpoints: github_endpoints(
+                    raw.github_web_base,
+                    raw.github_extra_web_bases.unwrap_or_default(),
+                    raw.github_api_base,
+                    raw.github_graphql_url,
+                ),
             },
+            sources,
+            sinks: raw.sinks.unwrap_or_else(|| vec![SinkConfig::Slack]),
+            rules: RuleSet::new(rules, digest_kinds),
+            digest,
+            interactivity: raw.interactivity.map(|i| InteractivityConfig {
+                listen: i.listen,
+                signing_secret: i.signing_secret,
+                snooze_hours: i.snooze_hours.unwrap_or(24),
+            }),
+            webhook: raw.webhook.map(|w| WebhookConfig {
+                listen: w.listen,
+                secret: w.secret,
+            }),
         })
     }
 }
 
+#[cfg(test)]
+impl Config {
+    // A config of a single profile for tests. `extra` is appended to the minimal TOML.
+    pub fn for_test(dir: PathBuf, extra: &str) -> Self {
+        let content = format!(
+            r#"
+            slack_oauth_bot_token = "xoxb-test"
+            slack_mail_channel_id = "C-mail"
+            slack_dest_channel_id = "C-dest"
+            github_access_token = "token"
+            github_login_name = "ryym"
+            {}
+            "#,
+            extra
+        );
+        let raw = RawConfig::parse_profiles(&content).unwrap().remove(0);
+        Config::build(dir, raw).unwrap()
+    }
+}
+
+// Fill the unspecified GitHub endpoints. If only the web base of GitHub Enterprise Server
+// is specified, its API endpoints are derived from it (`/api/v3` and `/api/graphql`).
+fn github_endpoints(
+    web_base: Option<String>,
+    extra_web_bases: Vec<String>,
+    api_base: Option<String>,
+    graphql_url: Option<String>,
+) -> github::Endpoints {
+    let defaults = github::Endpoints::default();
+    let web_base = web_base
+        .map(|w| w.trim_end_matches('/').to_string())
+        .unwrap_or(defaults.web_base);
+    let api_base = match api_base {
+        Some(api_base) => api_base.trim_end_matches('/').to_string(),
+        None if web_base == "https://github.com" => defaults.api_base,
+        None => format!("{}/api/v3", web_base),
+    };
+    let graphql_url = match graphql_url {
+        Some(url) => url,
+        None => match api_base.strip_suffix("/api/v3") {
+            Some(host) => format!("{}/api/graphql", host),
+            None => format!("{}/graphql", api_base),
+        },
+    };
+    github::Endpoints {
+        api_base,
+        graphql_url,
+        web_base,
+        extra_web_bases: extra_web_bases
+            .into_iter()
+            .map(|w| w.trim_end_matches('/').to_string())
+            .collect(),
+    }
+}
+
 #[derive(Debug)]
 pub struct SlackConfig {
     pub bot_token: String,
-    pub mail_channel_id: String,
+    pub api_base: String,
     pub dest_channel_id: String,
+    pub routes: Vec<Route>,
+    // Post notifications of the same issue as replies to the first one.
+    pub thread_replies: bool,
+    // Also show alerted thread replies in the channel.
+    pub reply_broadcast: bool,
+    pub message_format: MessageFormat,
+    // The max number of emails processed at once from a mail channel. The older ones beyond it
+    // are reported by a summary message instead.
+    pub catch_up_limit: usize,
+}
+
+const DEFAULT_CATCH_UP_LIMIT: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    // Plain mrkdwn text.
+    Text,
+    // Block Kit blocks with buttons.
+    Blocks,
+}
+
+impl SlackConfig {
+    // Find a destination channel by the repository. The first matching route wins.
+    pub fn dest_channel_for(&self, repo_fullname: Option<&str>) -> &str {
+        repo_fullname
+            .and_then(|name| self.routes.iter().find(|r| r.repo.is_match(name)))
+            .map(|r| r.channel.as_str())
+            .unwrap_or(&self.dest_channel_id)
+    }
+}
+
+#[derive(Debug)]
+pub struct Route {
+    pub repo: Glob,
+    pub channel: String,
+}
+
+#[derive(Debug)]
+pub struct DigestConfig {
+    pub schedule: digest::Schedule,
+    pub kinds: Vec<NotifKind>,
+}
+
+#[derive(Debug)]
+pub struct InteractivityConfig {
+    // An address to listen requests from Slack, such as `0.0.0.0:3000`.
+    pub listen: String,
+    pub signing_secret: String,
+    pub snooze_hours: u64,
+}
+
+#[derive(Debug)]
+pub struct WebhookConfig {
+    // An address to listen deliveries from GitHub, such as `0.0.0.0:3001`.
+    pub listen: String,
+    pub secret: String,
 }
 
 #[derive(Debug)]
 pub struct GitHubConfig {
     pub auth_token: String,
     pub login_name: String,
+    pub endpoints: github::Endpoints,
+}
+
+#[derive(Debug)]
+pub enum SourceConfig {
+    SlackChannel { channel_id: String },
+    Imap(ImapConfig),
+    // Poll the GitHub Notifications API instead of emails.
+    GitHubNotifications,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkConfig {
+    Slack,
+    Stdout,
+}
+
+#[derive(Debug)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub folder: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_inherit_top_level_keys() {
+        let content = r#"
+            slack_oauth_bot_token = "xoxb-shared"
+            slack_dest_channel_id = "C-shared"
+            github_access_token = "token-default"
+            github_login_name = "default"
+
+            [[profiles]]
+            github_access_token = "token-alice"
+            github_login_name = "alice"
+            slack_mail_channel_id = "C-alice-mail"
+
+            [[profiles]]
+            name = "bob-oncall"
+            github_access_token = "token-bob"
+            github_login_name = "bob"
+            slack_mail_channel_id = "C-bob-mail"
+            slack_dest_channel_id = "C-bob"
+        "#;
+        let configs = RawConfig::parse_profiles(content)
+            .unwrap()
+            .into_iter()
+            .map(|raw| Config::build(PathBuf::from("/tmp"), raw).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].profile, "alice");
+        assert_eq!(configs[0].slack.bot_token, "xoxb-shared");
+        assert_eq!(configs[0].slack.dest_channel_id, "C-shared");
+        assert_eq!(configs[0].github.auth_token, "token-alice");
+        assert_eq!(configs[1].profile, "bob-oncall");
+        assert_eq!(configs[1].slack.dest_channel_id, "C-bob");
+        assert_eq!(configs[1].github.login_name, "bob");
+    }
+
+    #[test]
+    fn no_profiles_means_single_profile() {
+        let content = r#"
+            slack_oauth_bot_token = "xoxb"
+            slack_mail_channel_id = "C-mail"
+            slack_dest_channel_id = "C-dest"
+            github_access_token = "token"
+            github_login_name = "ryym"
+        "#;
+        let raws = RawConfig::parse_profiles(content).unwrap();
+        assert_eq!(raws.len(), 1);
+        assert_eq!(raws[0].github_login_name, "ryym");
+    }
 }
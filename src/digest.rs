@@ -0,0 +1,151 @@
+//! Queues low-priority notifications and delivers them together on a schedule.
+
+use crate::{github, notif::Notification};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Hourly,
+    // Deliver once a day at the given hour in UTC.
+    Daily { hour: u64 },
+}
+
+impl Schedule {
+    // Get the first delivery time after `last_sent`, aligned to the hour boundaries.
+    pub fn next_after(&self, last_sent: u64) -> u64 {
+        match *self {
+            Schedule::Hourly => (last_sent / HOUR + 1) * HOUR,
+            Schedule::Daily { hour } => {
+                let time = last_sent / DAY * DAY + hour * HOUR;
+                if time <= last_sent {
+                    time + DAY
+                } else {
+                    time
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DigestState {
+    pub last_sent: u64,
+    pub items: Vec<DigestItem>,
+    // A digest being sent. It is kept until every sink delivers it.
+    #[serde(default)]
+    pub outbox: Vec<DigestItem>,
+    // The number of messages of the outbox each sink has sent.
+    #[serde(default)]
+    pub sent: HashMap<String, usize>,
+}
+
+impl DigestState {
+    pub fn new() -> Self {
+        DigestState {
+            last_sent: now(),
+            items: Vec::new(),
+            outbox: Vec::new(),
+            sent: HashMap::new(),
+        }
+    }
+
+    pub fn is_due(&self, schedule: Schedule) -> bool {
+        schedule.next_after(self.last_sent) <= now()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DigestItem {
+    pub notif: Notification,
+    // A destination channel decided by a rule, if any.
+    pub channel: Option<String>,
+}
+
+/// Notifications of a repository in a digest.
+#[derive(Debug)]
+pub struct RepoGroup<'a> {
+    pub repo_fullname: Option<String>,
+    pub threads: Vec<ThreadGroup<'a>>,
+}
+
+/// Notifications of an issue or a PR in a digest.
+/// Notifications not related to any issue (e.g. workflow results) are grouped without an issue.
+#[derive(Debug)]
+pub struct ThreadGroup<'a> {
+    pub issue: Option<&'a github::IssueInfo>,
+    pub notifs: Vec<&'a Notification>,
+}
+
+// Group notifications by repository and then by issue, keeping the order they arrived.
+pub fn group<'a>(notifs: &[&'a Notification]) -> Vec<RepoGroup<'a>> {
+    let mut repos: Vec<RepoGroup> = Vec::new();
+    for notif in notifs {
+        let repo_fullname = notif.detail.repo_fullname();
+        let issue = notif.detail.issue();
+
+        let repo = match repos.iter().position(|r| r.repo_fullname == repo_fullname) {
+            Some(idx) => &mut repos[idx],
+            None => {
+                repos.push(RepoGroup {
+                    repo_fullname,
+                    threads: Vec::new(),
+                });
+                repos.last_mut().unwrap()
+            }
+        };
+
+        let number = issue.map(|i| i.number);
+        match repo
+            .threads
+            .iter_mut()
+            .find(|t| t.issue.map(|i| i.number) == number)
+        {
+            Some(thread) => thread.notifs.push(notif),
+            None => repo.threads.push(ThreadGroup {
+                issue,
+                notifs: vec![notif],
+            }),
+        }
+    }
+    repos
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2021-06-01T10:30:00Z
+    const BASE: u64 = 1_622_543_400;
+
+    #[test]
+    fn hourly_schedule_aligns_to_next_hour() {
+        assert_eq!(Schedule::Hourly.next_after(BASE), BASE + 30 * 60);
+        assert_eq!(Schedule::Hourly.next_after(BASE + 30 * 60), BASE + 90 * 60);
+    }
+
+    #[test]
+    fn daily_schedule_runs_at_given_hour() {
+        let today_9 = BASE - 90 * 60;
+        let today_12 = BASE + 90 * 60;
+        assert_eq!(Schedule::Daily { hour: 12 }.next_after(BASE), today_12);
+        assert_eq!(Schedule::Daily { hour: 9 }.next_after(BASE), today_9 + DAY);
+        assert_eq!(
+            Schedule::Daily { hour: 12 }.next_after(today_12),
+            today_12 + DAY
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! A dead-letter queue of emails which failed to be parsed, stored as JSON files
+//! under `~/.gharry/dlq/<profile>` so that they can be inspected and retried later.
+//! The polling skips the messages found in the queue by their source and cursor.
+
+use crate::{config::Config, digest::now, email::Email, polling};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+#[derive(Debug)]
+pub struct Dlq {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    // The ID of the source which the email came from and its cursor there.
+    pub source: String,
+    pub cursor: String,
+    pub created_at: u64,
+    // The error and its causes from the outermost.
+    pub errors: Vec<String>,
+    pub email: Email,
+}
+
+impl Dlq {
+    pub fn open(config: &Config) -> Result<Self> {
+        Dlq::open_dir(config.state_path("dlq", &format!("dlq/{}", config.profile))?)
+    }
+
+    pub fn open_dir(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir).context("failed to create dead-letter directory")?;
+        Ok(Dlq { dir })
+    }
+
+    pub fn push(
+        &self,
+        source: &str,
+        cursor: &str,
+        email: Email,
+        err: &anyhow::Error,
+    ) -> Result<String> {
+        let created_at = now();
+        let id = (1..)
+            .map(|n| format!("{}-{}", created_at, n))
+            .find(|id| !self.path(id).exists())
+            .unwrap_or_default();
+        let entry = Entry {
+            id: id.clone(),
+            source: source.to_string(),
+            cursor: cursor.to_string(),
+            created_at,
+            errors: err.chain().map(|cause| cause.to_string()).collect(),
+            email,
+        };
+        let json = serde_json::to_string_pretty(&entry)?;
+        fs::write(self.path(&id), json).context("failed to store dead letter")?;
+        Ok(id)
+    }
+
+    // List the entries from oldest to newest.
+    pub fn list(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                entries.push(load_entry(&path)?);
+            }
+        }
+        entries.sort_by_key(|e| (e.created_at, e.id.clone()));
+        Ok(entries)
+    }
+
+    // The cursors of the messages from the source which are in the queue.
+    pub fn cursors(&self, source: &str) -> Result<HashSet<String>> {
+        let entries = self.list()?.into_iter();
+        Ok(entries
+            .filter(|e| e.source == source)
+            .map(|e| e.cursor)
+            .collect())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Entry> {
+        let path = self.path(id);
+        if !path.exists() {
+            return Err(anyhow!("dead letter not found: {}", id));
+        }
+        load_entry(&path)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<()> {
+        fs::remove_file(self.path(id)).with_context(|| format!("failed to remove {}", id))
+    }
+
+    pub fn update_errors(&self, id: &str, err: &anyhow::Error) -> Result<()> {
+        let mut entry = self.get(id)?;
+        entry.errors = err.chain().map(|cause| cause.to_string()).collect();
+        fs::write(self.path(id), serde_json::to_string_pretty(&entry)?)
+            .context("failed to store dead letter")
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+fn load_entry(path: &std::path::Path) -> Result<Entry> {
+    let content = fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+    serde_json::from_slice(&content).with_context(|| format!("invalid dead letter: {:?}", path))
+}
+
+const USAGE: &str = "usage: gharry dlq list | show <id> | retry <id>... | discard <id>...";
+
+// Run `gharry dlq` subcommands.
+pub fn run(config: &Config, args: &[String]) -> Result<()> {
+    let dlq = Dlq::open(config)?;
+    let ids = args.iter().skip(1).map(String::as_str).collect::<Vec<_>>();
+    match (args.first().map(String::as_str), ids.len()) {
+        (Some("list"), 0) => {
+            for entry in dlq.list()? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.id,
+                    entry.source,
+                    entry.email.subject,
+                    entry.errors.first().map_or("", |e| e.as_str())
+                );
+            }
+        }
+        (Some("show"), 1) => {
+            let entry = dlq.get(ids[0])?;
+            println!("{}", serde_json::to_string_pretty(&entry)?);
+        }
+        (Some("retry"), n) if n > 0 => polling::retry_dead_letters(config, &dlq, &ids)?,
+        (Some("discard"), n) if n > 0 => {
+            for id in ids {
+                dlq.remove(id)?;
+                log::info!("Discarded {}", id);
+            }
+        }
+        _ => return Err(anyhow!(USAGE)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn push_and_remove_entries() {
+        let dir = testing::temp_dir("dlq");
+        let dlq = Dlq::open_dir(dir).unwrap();
+
+        let email = |subject: &str| Email {
+            id: None,
+            subject: subject.to_string(),
+            sender_name: String::from("alice"),
+            text_body: String::from("@alice pushed 3 commits."),
+        };
+        let err = anyhow!("commit count mismatch").context("failed to parse push");
+        let id1 = dlq.push("imap-me", "1", email("first"), &err).unwrap();
+        let id2 = dlq.push("imap-me", "2", email("second"), &err).unwrap();
+        dlq.push("slack-C1", "1", email("other"), &err).unwrap();
+        assert_ne!(id1, id2);
+
+        let entries = dlq.list().unwrap();
+        let subjects = entries.iter().map(|e| e.email.subject.as_str());
+        assert_eq!(subjects.collect::<Vec<_>>(), ["first", "second", "other"]);
+        assert_eq!(
+            entries[0].errors,
+            ["failed to parse push", "commit count mismatch"]
+        );
+
+        dlq.remove(&id1).unwrap();
+        assert!(dlq.get(&id1).is_err());
+        let cursors = dlq.cursors("imap-me").unwrap();
+        assert_eq!(cursors, vec!["2".to_string()].into_iter().collect());
+    }
+}
@@ -1,6 +1,30 @@
-#[derive(Debug)]
+mod mailbox;
+mod rfc822;
+
+pub use mailbox::read_raw_messages;
+pub use rfc822::parse_message;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Email {
+    // An ID of the message. It is taken from the Message-ID header so that the same email
+    // has the same ID in any source. Only a Slack message without the header has its ts.
+    pub id: Option<String>,
     pub subject: String,
     pub sender_name: String,
     pub text_body: String,
 }
+
+// Build an email ID from a Message-ID header such as `<acme/widgets/pull/42/c4004@github.com>`.
+pub fn id_from_message_id(message_id: &str) -> Option<String> {
+    let id = message_id
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    if id.is_empty() {
+        None
+    } else {
+        Some(format!("email:{}", id))
+    }
+}
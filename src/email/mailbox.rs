@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Context, Result};
+use std::{fs, path::Path};
+
+/// Reads raw messages from a Maildir directory or an mbox file, from oldest to newest.
+pub fn read_raw_messages(path: &Path) -> Result<Vec<Vec<u8>>> {
+    if path.is_dir() {
+        read_maildir(path)
+    } else if path.is_file() {
+        read_mbox(path)
+    } else {
+        Err(anyhow!("mailbox not found: {}", path.display()))
+    }
+}
+
+fn read_maildir(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let mut entries = Vec::new();
+    for sub_dir in ["cur", "new"] {
+        let dir = path.join(sub_dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                entries.push(entry.path());
+            }
+        }
+    }
+    if entries.is_empty() && !path.join("cur").is_dir() && !path.join("new").is_dir() {
+        return Err(anyhow!("not a Maildir: {}", path.display()));
+    }
+
+    // Maildir file names start with the delivery timestamp so we can sort them by name.
+    entries.sort_by_key(|p| p.file_name().map(|n| n.to_os_string()));
+    entries
+        .into_iter()
+        .map(|p| fs::read(&p).with_context(|| format!("failed to read {}", p.display())))
+        .collect()
+}
+
+fn read_mbox(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    for line in content.split_inclusive(|b| *b == b'\n') {
+        // Each message starts with a "From " line (mboxrd).
+        if line.starts_with(b"From ") {
+            messages.extend(current.take());
+            current = Some(Vec::new());
+            continue;
+        }
+        if let Some(msg) = current.as_mut() {
+            // Unescape the quoted lines like ">From " or ">>From ".
+            let quotes = line.iter().take_while(|b| **b == b'>').count();
+            if quotes > 0 && line[quotes..].starts_with(b"From ") {
+                msg.extend_from_slice(&line[1..]);
+            } else {
+                msg.extend_from_slice(line);
+            }
+        }
+    }
+    messages.extend(current);
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn split_mbox_and_unescape_from_lines() {
+        let messages =
+            read_raw_messages(&testing::fixtures_dir("mailbox/notifications.mbox")).unwrap();
+        assert_eq!(messages.len(), 2);
+        let first = String::from_utf8(messages[0].clone()).unwrap();
+        assert!(first.starts_with("From: Alice Liddell"));
+        assert!(first.contains("\nFrom the start\n>From the middle\n"));
+        assert!(messages[1].starts_with(b"From: \"Bob\""));
+    }
+
+    #[test]
+    fn read_maildir_in_delivery_order() {
+        let messages = read_raw_messages(&testing::fixtures_dir("mailbox/maildir")).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with(b"From: Alice Liddell"));
+        assert!(messages[1].starts_with(b"From: \"Bob\""));
+    }
+}
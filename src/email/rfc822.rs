@@ -0,0 +1,296 @@
+use crate::email::Email;
+use anyhow::{anyhow, Context, Result};
+use encoding_rs::Encoding;
+use regex::Captures;
+
+#[derive(Debug)]
+struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    fn parse(text: &str) -> Self {
+        // Unfold the header lines that continue with leading whitespaces.
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in text.lines() {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                fields.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+        Headers { fields }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct ContentType {
+    mime_type: String,
+    boundary: Option<String>,
+    charset: Option<String>,
+}
+
+impl ContentType {
+    fn parse(value: Option<&str>) -> Self {
+        // The default content type is text/plain (RFC 2045).
+        let value = value.unwrap_or("text/plain");
+        let mut params = value.split(';');
+        let mime_type = params.next().unwrap_or("").trim().to_lowercase();
+        let params = params.filter_map(|p| p.split_once('=')).collect::<Vec<_>>();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.trim().trim_matches('"').to_string())
+        };
+        ContentType {
+            mime_type,
+            boundary: param("boundary"),
+            charset: param("charset"),
+        }
+    }
+}
+
+/// Parses a raw RFC 822 message into an [`Email`].
+/// Encoded bodies and words are decoded by their charsets, but unencoded 8-bit text
+/// must be UTF-8 as GitHub sends.
+pub fn parse_message(raw: &[u8]) -> Result<Email> {
+    let text = std::str::from_utf8(raw)
+        .context("email has 8-bit text not in UTF-8")?
+        .replace("\r\n", "\n");
+    let (headers, body) = split_header_and_body(&text);
+    let headers = Headers::parse(headers);
+
+    let subject = decode_encoded_words(headers.get("subject").unwrap_or(""))?;
+    let sender_name = match headers.get("from") {
+        Some(from) => sender_name_of(&decode_encoded_words(from)?),
+        None => return Err(anyhow!("no From header in email: {}", subject)),
+    };
+    let text_body = match extract_text_body(&headers, body)? {
+        Some(body) => body,
+        None => return Err(anyhow!("no text/plain part in email: {}", subject)),
+    };
+
+    Ok(Email {
+        id: headers
+            .get("message-id")
+            .and_then(super::id_from_message_id),
+        subject,
+        sender_name,
+        text_body,
+    })
+}
+
+fn split_header_and_body(text: &str) -> (&str, &str) {
+    match text.find("\n\n") {
+        Some(idx) => (&text[..idx], &text[(idx + 2)..]),
+        None => (text, ""),
+    }
+}
+
+// Get a display name from a From header such as `"Foo Bar" <notifications@github.com>`.
+// The address is used if there is no display name.
+fn sender_name_of(from: &str) -> String {
+    match from.rfind('<') {
+        Some(idx) if !from[..idx].trim().is_empty() => {
+            from[..idx].trim().trim_matches('"').to_string()
+        }
+        Some(idx) => from[(idx + 1)..].trim_end_matches('>').trim().to_string(),
+        None => from.trim().to_string(),
+    }
+}
+
+fn extract_text_body(headers: &Headers, body: &str) -> Result<Option<String>> {
+    let content_type = ContentType::parse(headers.get("content-type"));
+    if content_type.mime_type.starts_with("multipart/") {
+        let boundary = match content_type.boundary {
+            Some(boundary) => boundary,
+            None => return Ok(None),
+        };
+        for part in split_multipart(body, &boundary) {
+            let (headers, body) = split_header_and_body(part);
+            if let Some(text) = extract_text_body(&Headers::parse(headers), body)? {
+                return Ok(Some(text));
+            }
+        }
+        return Ok(None);
+    }
+    if content_type.mime_type != "text/plain" {
+        return Ok(None);
+    }
+    let encoding = headers
+        .get("content-transfer-encoding")
+        .unwrap_or("7bit")
+        .to_lowercase();
+    let bytes = match encoding.as_str() {
+        "quoted-printable" => decode_quoted_printable(body),
+        "base64" => {
+            let encoded = body.split_whitespace().collect::<String>();
+            match base64::decode(encoded) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    log::warn!("failed to decode base64 body: {}", err);
+                    return Ok(None);
+                }
+            }
+        }
+        // The text is already decoded as UTF-8.
+        _ => return Ok(Some(body.to_string())),
+    };
+    decode_charset(&bytes, content_type.charset.as_deref()).map(Some)
+}
+
+// Decode a text by its charset. A text without charset is US-ASCII (RFC 2045),
+// which we read as UTF-8 to tolerate a missing charset.
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> Result<String> {
+    let label = charset.unwrap_or("utf-8");
+    let encoding = match Encoding::for_label(label.as_bytes()) {
+        Some(encoding) => encoding,
+        None => return Err(anyhow!("unsupported charset: {}", label)),
+    };
+    Ok(encoding.decode_without_bom_handling(bytes).0.into_owned())
+}
+
+// Decode RFC 2047 encoded words such as `=?UTF-8?Q?Re:_[ryym/gharry]_=E3=81=82?=`.
+// Whitespaces between adjacent encoded words are ignored.
+fn decode_encoded_words(value: &str) -> Result<String> {
+    let re = regex!(r"=\?(?P<charset>[^?]+)\?(?P<enc>[QqBb])\?(?P<text>[^?]*)\?=");
+    let mut decoded = String::new();
+    let mut last_end = 0;
+    for caps in re.captures_iter(value) {
+        let m = caps.get(0).unwrap();
+        let gap = &value[last_end..m.start()];
+        if last_end == 0 || !gap.trim().is_empty() {
+            decoded.push_str(gap);
+        }
+        decoded.push_str(&decode_encoded_word(&caps)?);
+        last_end = m.end();
+    }
+    decoded.push_str(&value[last_end..]);
+    Ok(decoded)
+}
+
+fn decode_encoded_word(caps: &Captures) -> Result<String> {
+    let text = &caps["text"];
+    let bytes = match &caps["enc"] {
+        "B" | "b" => base64::decode(text).unwrap_or_else(|_| text.as_bytes().to_vec()),
+        _ => decode_quoted_printable(&text.replace('_', " ")),
+    };
+    // A charset may have a language suffix such as `UTF-8*en` (RFC 2231).
+    let charset = caps["charset"].split('*').next().unwrap_or("");
+    decode_charset(&bytes, Some(charset))
+}
+
+fn split_multipart<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut part_start: Option<usize> = None;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed.starts_with(&delimiter) {
+            if let Some(start) = part_start {
+                // Exclude the line break preceding the delimiter.
+                let end = if offset > start { offset - 1 } else { start };
+                parts.push(&body[start..end]);
+            }
+            if trimmed[delimiter.len()..].starts_with("--") {
+                break;
+            }
+            part_start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts
+}
+
+fn decode_quoted_printable(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'=' if bytes.get(idx + 1) == Some(&b'\n') => {
+                // A soft line break.
+                idx += 2;
+            }
+            b'=' => match bytes.get((idx + 1)..(idx + 3)).and_then(decode_hex) {
+                Some(b) => {
+                    decoded.push(b);
+                    idx += 3;
+                }
+                None => {
+                    decoded.push(b'=');
+                    idx += 1;
+                }
+            },
+            b => {
+                decoded.push(b);
+                idx += 1;
+            }
+        }
+    }
+    decoded
+}
+
+fn decode_hex(hex: &[u8]) -> Option<u8> {
+    let s = std::str::from_utf8(hex).ok()?;
+    u8::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{email::read_raw_messages, testing};
+
+    fn mbox_emails() -> Vec<Email> {
+        let path = testing::fixtures_dir("mailbox/notifications.mbox");
+        read_raw_messages(&path)
+            .unwrap()
+            .iter()
+            .map(|raw| parse_message(raw).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decode_encoded_words_by_charset() {
+        let email = &mbox_emails()[0];
+        assert_eq!(email.subject, "Re: [acme/widgets] Café (PR #42) à bientôt");
+        assert_eq!(email.sender_name, "Alice Liddell");
+        assert_eq!(
+            email.id.as_deref(),
+            Some("email:acme/widgets/pull/42/c4004@github.com")
+        );
+    }
+
+    #[test]
+    fn decode_base64_body_by_charset() {
+        let email = &mbox_emails()[1];
+        assert_eq!(email.sender_name, "Bob");
+        assert_eq!(email.text_body, "Merged #42 into main.\n\nDéjà vu.\n");
+    }
+
+    #[test]
+    fn reject_unknown_charsets_and_8bit_text_not_in_utf8() {
+        let raw = b"From: a@example.com\nSubject: =?X-UNKNOWN?Q?hi?=\n\nbody\n";
+        let err = parse_message(raw).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported charset: X-UNKNOWN");
+
+        let raw =
+            b"From: a@example.com\nContent-Type: text/plain; charset=ISO-8859-1\n\nD\xe9j\xe0 vu\n";
+        let err = parse_message(raw).unwrap_err();
+        assert_eq!(err.to_string(), "email has 8-bit text not in UTF-8");
+    }
+}
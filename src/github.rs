@@ -1,11 +1,14 @@
 mod api;
+mod cache;
+#[cfg(test)]
+pub mod fake;
 mod graphql;
 
 pub use api::Client;
+pub use graphql::SubscriptionState;
 
-use crate::email::Email;
+use crate::{email::Email, notif};
 use anyhow::Result;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
@@ -13,13 +16,35 @@ pub struct Credentials {
     pub auth_token: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// URLs of a GitHub instance, which can be GitHub Enterprise Server or a local stand-in.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub api_base: String,
+    pub graphql_url: String,
+    pub web_base: String,
+    // Other instances whose emails arrive at the same mailbox, such as github.com
+    // along with GitHub Enterprise Server. Links to them are recognized too.
+    pub extra_web_bases: Vec<String>,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            api_base: String::from("https://api.github.com"),
+            graphql_url: String::from("https://api.github.com/graphql"),
+            web_base: String::from("https://github.com"),
+            extra_web_bases: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct User {
     pub login: String,
     pub avatar_url: String,
 }
 
-#[derive(Debug, Deserialize, Clone, Serialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
 pub struct Repository {
     pub owner: String,
     pub name: String,
@@ -31,14 +56,14 @@ impl Repository {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct IssueInfo {
     pub repo: Repository,
     pub number: usize,
     pub title: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CommitInfo {
     pub hash: String,
     pub message: String,
@@ -46,12 +71,49 @@ pub struct CommitInfo {
 
 #[derive(Debug)]
 pub struct EmailNotif {
+    pub id: Option<String>,
     pub lines: Vec<String>,
     pub detected_issue: Option<IssueInfo>,
     pub github_url: Option<String>,
+    pub reason: Option<NotificationReason>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Why the user receives a notification, which GitHub writes in the email footer
+/// such as "You are receiving this because you were mentioned.".
+/// The names follow the reasons of the GitHub Notifications API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationReason {
+    Author,
+    Mention,
+    TeamMention,
+    ReviewRequested,
+    Subscribed,
+    Manual,
+    Assign,
+    StateChange,
+    CiActivity,
+    Comment,
+}
+
+impl NotificationReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Author => "you authored the thread",
+            Self::Mention => "you were mentioned",
+            Self::TeamMention => "your team was mentioned",
+            Self::ReviewRequested => "your review was requested",
+            Self::Subscribed => "you are subscribed",
+            Self::Manual => "you subscribed manually",
+            Self::Assign => "you were assigned",
+            Self::StateChange => "you changed the state",
+            Self::CiActivity => "the workflow ran on your branch",
+            Self::Comment => "you commented",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Issue {
     pub html_url: String,
     pub state: IssueState,
@@ -60,7 +122,7 @@ pub struct Issue {
     pub user: User,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum IssueState {
     Open,
@@ -71,13 +133,6 @@ pub enum IssueState {
 pub struct IssueEvent {
     pub event: String,
     pub actor: User,
-    pub issue: Issue,
-    pub pull_request: Option<IssueEventPr>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct IssueEventPr {
-    url: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -87,7 +142,7 @@ pub struct Review {
     pub state: ReviewState,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ReviewState {
     Commented,
@@ -96,6 +151,47 @@ pub enum ReviewState {
     Dismissed,
 }
 
+/// The current status of a PR, shown along with its notifications.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PrStatus {
+    pub is_draft: bool,
+    // The combined status of the CI checks of the head commit, if any.
+    pub checks: Option<CheckState>,
+    // Null if reviews are not required by the branch protection.
+    pub review_decision: Option<ReviewDecision>,
+    pub mergeable: MergeableState,
+    pub labels: Vec<String>,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CheckState {
+    Error,
+    Expected,
+    Failure,
+    Pending,
+    Success,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewDecision {
+    Approved,
+    ChangesRequested,
+    ReviewRequired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MergeableState {
+    Mergeable,
+    Conflicting,
+    // GitHub computes it in background after a push.
+    Unknown,
+}
+
 #[derive(Debug)]
 pub struct GetUserParams<'a> {
     pub name: &'a str,
@@ -144,6 +240,35 @@ pub struct ReviewComment {
     pub body: String,
 }
 
+#[derive(Debug)]
+pub struct GetPrStatusParams<'a> {
+    pub repo: &'a Repository,
+    pub number: usize,
+}
+
+#[derive(Debug)]
+pub struct CompareCommitsParams<'a> {
+    pub repo: &'a Repository,
+    pub base: &'a str,
+    pub head: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Comparison {
+    pub commits: Vec<ComparedCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComparedCommit {
+    pub sha: String,
+    pub commit: CommitMessage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitMessage {
+    pub message: String,
+}
+
 #[derive(Debug)]
 pub struct UnsubscribePrParams<'a> {
     pub repo: &'a Repository,
@@ -151,7 +276,98 @@ pub struct UnsubscribePrParams<'a> {
     pub user_login: &'a str,
 }
 
-pub fn build_notif_from_email(email: &Email) -> Result<EmailNotif> {
+#[derive(Debug)]
+pub struct ListNotificationsParams<'a> {
+    // Only the threads updated after this time (ISO 8601) are listed.
+    pub since: &'a str,
+    // The `Last-Modified` of the previous response.
+    pub if_modified_since: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct ListNotificationsResponse {
+    // `None` if nothing is modified since the previous request.
+    pub threads: Option<Vec<NotificationThread>>,
+    pub last_modified: Option<String>,
+    // How many seconds we should wait before the next request.
+    pub poll_interval: Option<u64>,
+}
+
+/// A notification thread of the GitHub Notifications API.
+#[derive(Debug, Deserialize)]
+pub struct NotificationThread {
+    pub id: String,
+    pub reason: String,
+    pub updated_at: String,
+    pub subject: ThreadSubject,
+    pub repository: ThreadRepository,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadSubject {
+    pub title: String,
+    pub url: Option<String>,
+    pub latest_comment_url: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadRepository {
+    pub name: String,
+    pub owner: ThreadRepositoryOwner,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadRepositoryOwner {
+    pub login: String,
+}
+
+#[derive(Debug)]
+pub struct MarkThreadReadParams<'a> {
+    pub thread_id: &'a str,
+}
+
+#[derive(Debug)]
+pub struct UpdatePrSubscriptionParams<'a> {
+    pub repo: &'a Repository,
+    pub number: usize,
+    pub state: SubscriptionState,
+}
+
+/// A resource which a notification parser needs, so that the resources of many emails
+/// can be fetched at once before parsing them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    User {
+        login: String,
+    },
+    Issue {
+        repo: Repository,
+        number: usize,
+    },
+    IssueComment {
+        repo: Repository,
+        number: usize,
+        comment_id: usize,
+    },
+    PrReview {
+        repo: Repository,
+        pr_number: usize,
+        review_id: usize,
+    },
+    PrReviewComment {
+        repo: Repository,
+        pr_number: usize,
+        review_comment_id: usize,
+    },
+    PrStatus {
+        repo: Repository,
+        number: usize,
+    },
+}
+
+pub fn build_notif_from_email(email: &Email, web_bases: &[&str]) -> Result<EmailNotif> {
     let lines = email
         .text_body
         .replace('\r', "")
@@ -159,30 +375,55 @@ pub fn build_notif_from_email(email: &Email) -> Result<EmailNotif> {
         .map(|s| s.to_string())
         .collect::<Vec<_>>();
     let issue = issue_info_from_notif_subject(&email.subject)?;
-    let github_url = find_github_link(&lines);
+    let github_url = find_github_link(&lines, web_bases);
+    let reason = find_notification_reason(&lines);
+    // The URL anchors such as `#issuecomment-123` are the same in any source,
+    // while some emails like review requests link to the PR itself.
+    let id = github_url
+        .as_deref()
+        .and_then(anchor_id)
+        .or_else(|| email.id.clone());
 
     Ok(EmailNotif {
+        id,
         lines,
         detected_issue: issue,
         github_url,
+        reason,
     })
 }
 
+/// Get an ID from the anchor of a GitHub URL, such as `#issuecomment-123`, `#pullrequestreview-123`
+/// or `#event-123`. They are unique across repositories.
+pub fn anchor_id(url: &str) -> Option<String> {
+    match url.split_once('#') {
+        Some((_, anchor)) if !anchor.is_empty() => Some(format!("github:{}", anchor)),
+        _ => None,
+    }
+}
+
 fn issue_info_from_notif_subject(subject: &str) -> Result<Option<IssueInfo>> {
     // A regex matches with a subject such as "Re: [ryym/gharry] Fix typo (#1234)".
     // Note that some email subjects end with "(PR #1234)" instead of "(#1234)" since 2021-10-18.
-    let re = Regex::new(
+    let re = regex!(
         r"^(?:Re: )?\[(?P<owner>[^/]+)/(?P<repo>[^\]]+)\] (?P<title>.+) \((?:PR )?#(?P<issue>\d+)\)$",
-    )?;
+    );
     match re.captures(subject) {
         None => Ok(None),
         Some(caps) => {
+            // A subject with a broken number cannot be notified even as a plain message.
+            let number = caps["issue"]
+                .parse()
+                .map_err(|_| notif::ParseError::InvalidNumber {
+                    name: "issue number",
+                    value: caps["issue"].to_string(),
+                })?;
             let issue = IssueInfo {
                 repo: Repository {
                     owner: caps["owner"].to_string(),
                     name: caps["repo"].to_string(),
                 },
-                number: caps["issue"].parse().unwrap(),
+                number,
                 title: caps["title"].to_string(),
             };
             Ok(Some(issue))
@@ -190,19 +431,91 @@ fn issue_info_from_notif_subject(subject: &str) -> Result<Option<IssueInfo>> {
     }
 }
 
-fn find_github_link(lines: &[String]) -> Option<String> {
-    let idx_last = lines.len() - 1;
-    let from = std::cmp::max(idx_last - 6, 0);
+fn find_github_link(lines: &[String], web_bases: &[&str]) -> Option<String> {
+    let idx_last = lines.len().saturating_sub(1);
+    let from = idx_last.saturating_sub(6);
     for idx in from..idx_last {
         let line = &lines[idx];
         if line.starts_with("Reply to this email directly or view it on GitHub:")
             || line.starts_with("View it on GitHub:")
         {
             let maybe_url = lines[idx + 1].trim();
-            if maybe_url.starts_with("https://github.com") {
+            // Check the slash after the host so that `https://github.com.example` does not match.
+            let is_github_url = web_bases.iter().any(|base| {
+                maybe_url
+                    .strip_prefix(base)
+                    .is_some_and(|path| path.starts_with('/'))
+            });
+            if is_github_url {
                 return Some(maybe_url.to_string());
             }
         }
     }
     None
 }
+
+fn find_notification_reason(lines: &[String]) -> Option<NotificationReason> {
+    use NotificationReason::*;
+    const PHRASES: [(&str, NotificationReason); 11] = [
+        ("you authored the thread", Author),
+        ("you were mentioned", Mention),
+        ("you are on a team that was mentioned", TeamMention),
+        ("your review was requested", ReviewRequested),
+        (
+            "you are on a team that was requested to review",
+            ReviewRequested,
+        ),
+        ("you are subscribed to this thread", Subscribed),
+        ("you subscribed to this thread", Manual),
+        ("you were assigned", Assign),
+        ("you modified the open/close state", StateChange),
+        ("this workflow ran on your branch", CiActivity),
+        ("you commented", Comment),
+    ];
+
+    // The footer is at the end so search from the last line.
+    let footer = lines.iter().rev().find_map(|l| {
+        l.strip_prefix("You are receiving this because ")
+            .or_else(|| l.strip_prefix("You are receiving this email because "))
+    })?;
+    let reason = PHRASES
+        .iter()
+        .find(|(phrase, _)| footer.starts_with(phrase))
+        .map(|(_, reason)| *reason);
+    if reason.is_none() {
+        log::debug!("unknown notification reason: {}", footer);
+    }
+    reason
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_github_link_in_short_emails() {
+        let lines = |ls: &[&str]| ls.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let web_bases = ["https://github.com"];
+        assert_eq!(find_github_link(&[], &web_bases), None);
+        assert_eq!(find_github_link(&lines(&["Hi"]), &web_bases), None);
+        assert_eq!(
+            find_github_link(
+                &lines(&["View it on GitHub:", "https://github.com/a/b/pull/1"]),
+                &web_bases
+            ),
+            Some(String::from("https://github.com/a/b/pull/1"))
+        );
+    }
+
+    #[test]
+    fn find_github_link_of_configured_hosts_only() {
+        let find = |url: &str| {
+            let lines = vec![String::from("View it on GitHub:"), url.to_string()];
+            find_github_link(&lines, &["https://ghe.example.com", "https://github.com"])
+        };
+        assert!(find("https://ghe.example.com/a/b/pull/1").is_some());
+        assert!(find("https://github.com/a/b/pull/1").is_some());
+        assert_eq!(find("https://github.com.evil/a/b/pull/1"), None);
+        assert_eq!(find("https://ghe.example.com.evil/a/b/pull/1"), None);
+    }
+}
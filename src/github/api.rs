@@ -1,26 +1,65 @@
 use crate::{
-    github::{self, graphql},
-    web,
+    digest::now,
+    github::{
+        self,
+        cache::{self, ResponseCache},
+        graphql,
+    },
+    web::{self, retry::RetryPolicy},
 };
 use anyhow::{anyhow, Result};
-use reqwest::StatusCode;
+use reqwest::{header, StatusCode};
 use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+// How many users and issues are looked up by one GraphQL query.
+const PREFETCH_CHUNK_SIZE: usize = 50;
 
 #[derive(Debug)]
 pub struct Client {
     client: reqwest::blocking::Client,
-}
-#[derive(Debug, Serialize)]
-struct GetReviewRequestsVariables<'a> {
-    owner: &'a str,
-    repo: &'a str,
-    pr_number: usize,
+    endpoints: github::Endpoints,
+    retry: RetryPolicy,
+    cache: RefCell<ResponseCache>,
+    // The resources fetched by `prefetch` in the shape of REST responses, keyed by their URLs.
+    prefetched: RefCell<HashMap<String, serde_json::Value>>,
 }
 
 impl Client {
-    pub fn new(creds: github::Credentials) -> Result<Self> {
+    pub fn new(creds: github::Credentials, endpoints: github::Endpoints) -> Result<Self> {
         let client = Self::build_inner_client(creds)?;
-        Ok(Client { client })
+        Ok(Client {
+            client,
+            endpoints,
+            retry: RetryPolicy::default(),
+            cache: RefCell::new(ResponseCache::default()),
+            prefetched: RefCell::new(HashMap::new()),
+        })
+    }
+
+    // Keep the cached lookups in a file so that they survive restarts.
+    pub fn with_cache_file(mut self, path: PathBuf) -> Result<Self> {
+        self.cache = RefCell::new(ResponseCache::load(path)?);
+        Ok(self)
+    }
+
+    // Write the cached lookups to the file, if the cache is kept in a file.
+    pub fn save_cache(&self) -> Result<()> {
+        self.cache.borrow_mut().save()
+    }
+
+    pub fn web_base(&self) -> &str {
+        &self.endpoints.web_base
+    }
+
+    // The web bases whose links are recognized in emails, starting with the primary one.
+    pub fn web_bases(&self) -> Vec<&str> {
+        let extra = self.endpoints.extra_web_bases.iter().map(String::as_str);
+        std::iter::once(self.web_base()).chain(extra).collect()
     }
 
     fn build_inner_client(creds: github::Credentials) -> Result<reqwest::blocking::Client> {
@@ -40,54 +79,79 @@ impl Client {
     }
 
     pub fn get_user(&self, params: &github::GetUserParams) -> Result<Option<github::User>> {
-        let url = format!("https://api.github.com/users/{}", params.name);
-        let res = self.client.get(&url).send()?;
+        let url = self.user_url(params.name);
+        self.get_cached(url, cache::USER_TTL_SECS)
+            .map_err(|err| err.context("failed to fetch user"))
+    }
 
-        match res.status() {
-            StatusCode::OK => Ok(res.json()?),
-            StatusCode::NOT_FOUND => Ok(None),
-            _ => {
-                web::log_error_response(&url, res);
-                Err(anyhow!("failed to fetch user: {}", url))
+    pub fn get_issue(&self, params: &github::GetIssueParams) -> Result<Option<github::Issue>> {
+        let url = self.issue_url(&params.repo.owner, &params.repo.name, params.number);
+        self.get_cached(url, cache::ISSUE_TTL_SECS)
+            .map_err(|err| err.context("failed to fetch issue"))
+    }
+
+    // Get a resource from the cache if it is fresh. Otherwise fetch it, conditionally
+    // by its ETag if cached, and cache it. A missing resource is not cached.
+    fn get_cached<T: Serialize + DeserializeOwned>(
+        &self,
+        url: String,
+        ttl_secs: u64,
+    ) -> Result<Option<T>> {
+        let now = now();
+        if let Some(value) = self.get_prefetched(&url) {
+            let entry = cache::Entry {
+                etag: None,
+                fetched_at: now,
+                value: value.clone(),
+            };
+            self.cache.borrow_mut().insert(url, entry);
+            return Ok(Some(serde_json::from_value(value)?));
+        }
+        let cached = self.cache.borrow().get(&url).cloned();
+        if let Some(entry) = &cached {
+            if entry.is_fresh(now, ttl_secs) {
+                return Ok(Some(serde_json::from_value(entry.value.clone())?));
             }
         }
-    }
 
-    pub fn get_issue(&self, params: &github::GetIssueParams) -> Result<Option<github::Issue>> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/issues/{}",
-            params.repo.owner, params.repo.name, params.number
-        );
-        let res = self.client.get(&url).send()?;
+        let mut req = self.client.get(&url);
+        if let Some(etag) = cached.as_ref().and_then(|e| e.etag.as_deref()) {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        let res = self.retry.send(req)?;
 
-        match res.status() {
-            StatusCode::OK => Ok(res.json()?),
-            StatusCode::NOT_FOUND => Ok(None),
+        let (etag, value) = match (res.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(entry)) => (entry.etag, entry.value),
+            (StatusCode::OK, _) => {
+                let etag = res
+                    .headers()
+                    .get(header::ETAG)
+                    .and_then(|v| v.to_str().ok())
+                    .map(String::from);
+                let value = serde_json::to_value(res.json::<T>()?)?;
+                (etag, value)
+            }
+            (StatusCode::NOT_FOUND, _) => return Ok(None),
             _ => {
                 web::log_error_response(&url, res);
-                Err(anyhow!("failed to fetch issue: {}", url))
+                return Err(anyhow!("unexpected response: {}", url));
             }
-        }
+        };
+        let entry = cache::Entry {
+            etag,
+            fetched_at: now,
+            value: value.clone(),
+        };
+        self.cache.borrow_mut().insert(url, entry);
+        Ok(Some(serde_json::from_value(value)?))
     }
 
     pub fn get_issue_comment(
         &self,
         params: &github::GetIssueCommentParams,
     ) -> Result<Option<github::IssueComment>> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/issues/comments/{}",
-            params.repo.owner, params.repo.name, params.comment_id,
-        );
-        let res = self.client.get(&url).send()?;
-
-        match res.status() {
-            StatusCode::OK => Ok(res.json()?),
-            StatusCode::NOT_FOUND => Ok(None),
-            _ => {
-                web::log_error_response(&url, res);
-                Err(anyhow!("failed to fetch issue comment: {}", url))
-            }
-        }
+        let url = self.issue_comment_url(&params.repo.owner, &params.repo.name, params.comment_id);
+        self.get_prefetched_or(url, "failed to fetch issue comment")
     }
 
     pub fn get_issue_event(
@@ -95,10 +159,10 @@ impl Client {
         params: &github::GetIssueEventParams,
     ) -> Result<Option<github::IssueEvent>> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/issues/events/{}",
-            params.repo.owner, params.repo.name, params.event_id,
+            "{}/repos/{}/{}/issues/events/{}",
+            self.endpoints.api_base, params.repo.owner, params.repo.name, params.event_id,
         );
-        let res = self.client.get(&url).send()?;
+        let res = self.retry.send(self.client.get(&url))?;
 
         match res.status() {
             StatusCode::OK => Ok(res.json()?),
@@ -114,47 +178,383 @@ impl Client {
         &self,
         params: &github::GetPrReviewParams,
     ) -> Result<Option<github::Review>> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/pulls/{}/reviews/{}",
-            params.repo.owner, params.repo.name, params.pr_number, params.review_id,
+        let url = self.pr_review_url(
+            &params.repo.owner,
+            &params.repo.name,
+            params.pr_number,
+            params.review_id,
         );
-        let res = self.client.get(&url).send()?;
+        self.get_prefetched_or(url, "failed to fetch PR review")
+    }
 
+    pub fn get_pr_review_comment(
+        &self,
+        params: &github::GetPrReviewCommentParams,
+    ) -> Result<Option<github::ReviewComment>> {
+        let url = self.pr_review_comment_url(
+            &params.repo.owner,
+            &params.repo.name,
+            params.review_comment_id,
+        );
+        self.get_prefetched_or(url, "failed to fetch PR review comment")
+    }
+
+    // Get a resource fetched by `prefetch`, or fetch it now if not prefetched.
+    fn get_prefetched_or<T: DeserializeOwned>(
+        &self,
+        url: String,
+        failure: &str,
+    ) -> Result<Option<T>> {
+        if let Some(value) = self.get_prefetched(&url) {
+            return Ok(Some(serde_json::from_value(value)?));
+        }
+        let res = self.retry.send(self.client.get(&url))?;
         match res.status() {
             StatusCode::OK => Ok(res.json()?),
             StatusCode::NOT_FOUND => Ok(None),
             _ => {
                 web::log_error_response(&url, res);
-                Err(anyhow!("failed to fetch PR review: {}", url))
+                Err(anyhow!("{}: {}", failure, url))
             }
         }
     }
 
-    pub fn get_pr_review_comment(
-        &self,
-        params: &github::GetPrReviewCommentParams,
-    ) -> Result<Option<github::ReviewComment>> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/pulls/comments/{}",
-            params.repo.owner, params.repo.name, params.review_comment_id,
+    // The prefetched resources are kept until `clear_prefetched` since emails in a batch
+    // may need the same ones, such as the status of a PR.
+    fn get_prefetched(&self, url: &str) -> Option<serde_json::Value> {
+        self.prefetched.borrow().get(url).cloned()
+    }
+
+    fn user_url(&self, login: &str) -> String {
+        format!("{}/users/{}", self.endpoints.api_base, login)
+    }
+
+    fn repo_url(&self, owner: &str, repo: &str) -> String {
+        format!("{}/repos/{}/{}", self.endpoints.api_base, owner, repo)
+    }
+
+    fn issue_url(&self, owner: &str, repo: &str, number: usize) -> String {
+        format!("{}/issues/{}", self.repo_url(owner, repo), number)
+    }
+
+    fn issue_comment_url(&self, owner: &str, repo: &str, comment_id: usize) -> String {
+        format!(
+            "{}/issues/comments/{}",
+            self.repo_url(owner, repo),
+            comment_id
+        )
+    }
+
+    fn pr_review_url(&self, owner: &str, repo: &str, pr_number: usize, review_id: usize) -> String {
+        format!(
+            "{}/pulls/{}/reviews/{}",
+            self.repo_url(owner, repo),
+            pr_number,
+            review_id
+        )
+    }
+
+    fn pr_review_comment_url(&self, owner: &str, repo: &str, review_comment_id: usize) -> String {
+        format!(
+            "{}/pulls/comments/{}",
+            self.repo_url(owner, repo),
+            review_comment_id
+        )
+    }
+
+    // PR statuses have no REST URLs, so they are prefetched by this key instead.
+    fn pr_status_key(&self, owner: &str, repo: &str, number: usize) -> String {
+        format!("{}/pulls/{}#status", self.repo_url(owner, repo), number)
+    }
+
+    // Fetch the resources needed to parse a batch of emails by a few GraphQL queries
+    // instead of a REST request per email. The `get_*` methods use the prefetched ones
+    // and fall back to the REST API for the others, such as the ones not found by the queries.
+    pub fn prefetch(&self, lookups: &[github::Lookup]) -> Result<()> {
+        use github::Lookup;
+
+        self.prefetched.borrow_mut().clear();
+        let now = now();
+        let is_cached = |url: &str, ttl_secs: u64| {
+            let cache = self.cache.borrow();
+            cache.get(url).is_some_and(|e| e.is_fresh(now, ttl_secs))
+        };
+
+        let mut users = BTreeSet::new();
+        let mut issues = BTreeMap::new();
+        fn issue<'a, 'b>(
+            issues: &'b mut BTreeMap<(&'a str, &'a str, usize), graphql::IssueLookup<'a>>,
+            repo: &'a github::Repository,
+            number: usize,
+        ) -> &'b mut graphql::IssueLookup<'a> {
+            let key = (repo.owner.as_str(), repo.name.as_str(), number);
+            issues.entry(key).or_insert(graphql::IssueLookup {
+                owner: &repo.owner,
+                repo: &repo.name,
+                number,
+                comments: false,
+                reviews: false,
+                status: false,
+            })
+        }
+        for lookup in lookups {
+            match lookup {
+                Lookup::User { login } => {
+                    if !is_cached(&self.user_url(login), cache::USER_TTL_SECS) {
+                        users.insert(login.as_str());
+                    }
+                }
+                Lookup::Issue { repo, number } => {
+                    let url = self.issue_url(&repo.owner, &repo.name, *number);
+                    if !is_cached(&url, cache::ISSUE_TTL_SECS) {
+                        issue(&mut issues, repo, *number);
+                    }
+                }
+                Lookup::IssueComment { repo, number, .. } => {
+                    issue(&mut issues, repo, *number).comments = true
+                }
+                Lookup::PrReview {
+                    repo, pr_number, ..
+                }
+                | Lookup::PrReviewComment {
+                    repo, pr_number, ..
+                } => issue(&mut issues, repo, *pr_number).reviews = true,
+                Lookup::PrStatus { repo, number } => {
+                    issue(&mut issues, repo, *number).status = true
+                }
+            }
+        }
+        if users.is_empty() && issues.is_empty() {
+            return Ok(());
+        }
+        log::debug!(
+            "prefetch {} users and {} issues by GraphQL",
+            users.len(),
+            issues.len()
         );
-        let res = self.client.get(&url).send()?;
 
-        match res.status() {
-            StatusCode::OK => Ok(res.json()?),
-            StatusCode::NOT_FOUND => Ok(None),
+        let users = users.into_iter().collect::<Vec<_>>();
+        let issues = issues.into_values().collect::<Vec<_>>();
+        let mut user_chunks = users.chunks(PREFETCH_CHUNK_SIZE);
+        let mut issue_chunks = issues.chunks(PREFETCH_CHUNK_SIZE);
+        loop {
+            let query = graphql::BatchLookupQuery {
+                users: user_chunks.next().unwrap_or_default().to_vec(),
+                issues: issue_chunks.next().unwrap_or_default().to_vec(),
+            };
+            if query.users.is_empty() && query.issues.is_empty() {
+                return Ok(());
+            }
+            self.prefetch_batch(&query)?;
+        }
+    }
+
+    fn prefetch_batch(&self, query: &graphql::BatchLookupQuery) -> Result<()> {
+        use graphql::batch_lookup as batch;
+
+        let r = graphql::send(
+            &self.client,
+            &self.retry,
+            &self.endpoints.graphql_url,
+            query,
+        )?;
+        // Users or issues not found are reported as errors along with the others found.
+        if let Some(errs) = &r.errors {
+            log::debug!("prefetch errors: {:?}", errs);
+        }
+        let mut data = match r.data {
+            Some(data) => data,
+            None => return Err(anyhow!("failed to prefetch: no data")),
+        };
+
+        let user_json = |user: &batch::User| serde_json::json!({ "login": user.login, "avatar_url": user.avatar_url });
+        let mut prefetched = self.prefetched.borrow_mut();
+
+        for (i, login) in query.users.iter().enumerate() {
+            let value = data.remove(&format!("u{}", i));
+            if let Some(user) = value.and_then(|v| serde_json::from_value::<batch::User>(v).ok()) {
+                prefetched.insert(self.user_url(login), user_json(&user));
+            }
+        }
+
+        for (i, lookup) in query.issues.iter().enumerate() {
+            let value = data.remove(&format!("i{}", i));
+            let repo = match value.and_then(|v| serde_json::from_value::<batch::Repository>(v).ok())
+            {
+                Some(repo) => repo,
+                None => continue,
+            };
+            let (owner, name) = (lookup.owner, lookup.repo);
+            if let Some(pr) = repo.status {
+                let status = serde_json::to_value(pr.into_status())?;
+                prefetched.insert(self.pr_status_key(owner, name, lookup.number), status);
+            }
+            let issue = match repo.issue_or_pull_request {
+                Some(issue) => issue,
+                None => continue,
+            };
+
+            let comments = issue.comments.into_iter().flat_map(|c| c.nodes).flatten();
+            for comment in comments {
+                if let Some((id, author)) = comment_id_and_author(&comment) {
+                    prefetched.insert(
+                        self.issue_comment_url(owner, name, id),
+                        serde_json::json!({ "body": comment.body, "user": user_json(author) }),
+                    );
+                }
+            }
+
+            let reviews = issue.reviews.into_iter().flat_map(|c| c.nodes).flatten();
+            for review in reviews {
+                let comments = review.comments.iter().flat_map(|c| &c.nodes).flatten();
+                for comment in comments {
+                    if let Some((id, author)) = comment_id_and_author(comment) {
+                        prefetched.insert(
+                            self.pr_review_comment_url(owner, name, id),
+                            serde_json::json!({ "body": comment.body, "user": user_json(author) }),
+                        );
+                    }
+                }
+                // Pending reviews are not visible in emails.
+                let state = serde_json::json!(review.state);
+                if serde_json::from_value::<github::ReviewState>(state.clone()).is_err() {
+                    continue;
+                }
+                let id = review.full_database_id.and_then(|id| id.parse().ok());
+                if let (Some(id), Some(author)) = (id, &review.author) {
+                    prefetched.insert(
+                        self.pr_review_url(owner, name, issue.number, id),
+                        serde_json::json!({
+                            "user": user_json(author),
+                            "body": review.body,
+                            "state": state,
+                        }),
+                    );
+                }
+            }
+
+            if let Some(author) = &issue.author {
+                let state = if issue.state == "OPEN" {
+                    "open"
+                } else {
+                    "closed"
+                };
+                prefetched.insert(
+                    self.issue_url(owner, name, issue.number),
+                    serde_json::json!({
+                        "html_url": issue.url,
+                        "state": state,
+                        "number": issue.number,
+                        "title": issue.title,
+                        "user": user_json(author),
+                    }),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Forget the prefetched resources after parsing the batch.
+    pub fn clear_prefetched(&self) {
+        self.prefetched.borrow_mut().clear();
+    }
+
+    pub fn list_notifications(
+        &self,
+        params: &github::ListNotificationsParams,
+    ) -> Result<github::ListNotificationsResponse> {
+        let url = format!("{}/notifications", self.endpoints.api_base);
+        let mut req = self.client.get(&url).query(&[("since", params.since)]);
+        if let Some(last_modified) = params.if_modified_since {
+            req = req.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        let res = self.retry.send(req)?;
+
+        let header = |name: &str| {
+            res.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+        let last_modified = header("Last-Modified");
+        let poll_interval = header("X-Poll-Interval").and_then(|v| v.parse().ok());
+
+        let threads = match res.status() {
+            StatusCode::OK => Some(res.json()?),
+            StatusCode::NOT_MODIFIED => None,
             _ => {
                 web::log_error_response(&url, res);
-                Err(anyhow!("failed to fetch PR review: {}", url))
+                return Err(anyhow!("failed to list notifications: {}", url));
             }
+        };
+        Ok(github::ListNotificationsResponse {
+            threads,
+            last_modified,
+            poll_interval,
+        })
+    }
+
+    // List the commits from `base` to `head`, oldest first.
+    pub fn compare_commits(
+        &self,
+        params: &github::CompareCommitsParams,
+    ) -> Result<Vec<github::CommitInfo>> {
+        let url = format!(
+            "{}/repos/{}/{}/compare/{}...{}",
+            self.endpoints.api_base, params.repo.owner, params.repo.name, params.base, params.head
+        );
+        let res = self.retry.send(self.client.get(&url))?;
+        if res.status() != StatusCode::OK {
+            web::log_error_response(&url, res);
+            return Err(anyhow!("failed to compare commits: {}", url));
+        }
+        let comparison: github::Comparison = res.json()?;
+        let commits = comparison.commits.into_iter().map(|c| github::CommitInfo {
+            hash: c.sha,
+            message: c.commit.message,
+        });
+        Ok(commits.collect())
+    }
+
+    pub fn mark_thread_read(&self, params: &github::MarkThreadReadParams) -> Result<()> {
+        let url = format!(
+            "{}/notifications/threads/{}",
+            self.endpoints.api_base, params.thread_id
+        );
+        let res = self.retry.send(self.client.patch(&url))?;
+        if !res.status().is_success() {
+            web::log_error_response(&url, res);
+            return Err(anyhow!("failed to mark thread as read: {}", url));
         }
+        Ok(())
     }
 
     fn send_graphql<O: DeserializeOwned>(
         &self,
         query: &impl graphql::Query<Output = O>,
     ) -> Result<O> {
-        graphql::fetch_data(&self.client, "https://api.github.com/graphql", query)
+        graphql::fetch_data(
+            &self.client,
+            &self.retry,
+            &self.endpoints.graphql_url,
+            query,
+        )
+    }
+
+    pub fn get_pr_status(
+        &self,
+        params: &github::GetPrStatusParams,
+    ) -> Result<Option<github::PrStatus>> {
+        let (owner, repo) = (&params.repo.owner, &params.repo.name);
+        if let Some(value) = self.get_prefetched(&self.pr_status_key(owner, repo, params.number)) {
+            return Ok(Some(serde_json::from_value(value)?));
+        }
+        let data = self.send_graphql(&graphql::PrStatusQuery {
+            owner,
+            repo,
+            pr_number: params.number,
+        })?;
+        Ok(data.into_pull_request().map(|pr| pr.into_status()))
     }
 
     pub fn unsubscribe_pr(&self, params: &github::UnsubscribePrParams) -> Result<bool> {
@@ -181,19 +581,206 @@ impl Client {
             return Ok(false);
         }
 
+        self.update_subscription(&pr.id, graphql::SubscriptionState::Unsubscribed)
+    }
+
+    // Update the subscription of a PR regardless of the review requests.
+    pub fn update_pr_subscription(
+        &self,
+        params: &github::UpdatePrSubscriptionParams,
+    ) -> Result<bool> {
+        let data = self.send_graphql(&graphql::GetReviewRequestsQuery {
+            owner: &params.repo.owner,
+            repo: &params.repo.name,
+            pr_number: params.number,
+        })?;
+        match data.pull_request() {
+            Some(pr) => self.update_subscription(&pr.id, params.state),
+            None => Ok(false),
+        }
+    }
+
+    fn update_subscription(
+        &self,
+        subscribable_id: &str,
+        state: graphql::SubscriptionState,
+    ) -> Result<bool> {
         let data = self.send_graphql(&graphql::UpdateSubscriptionMut {
             input: graphql::UpdateSubscriptionInput {
-                state: graphql::SubscriptionState::Unsubscribed,
-                subscribable_id: pr.id.clone(),
+                state,
+                subscribable_id: subscribable_id.to_string(),
             },
         })?;
 
         let updated_state = data.viewer_subscription();
-        log::debug!("unsubscribed: {:?}", updated_state);
+        log::debug!("updated subscription: {:?}", updated_state);
+
+        Ok(updated_state == Some(state))
+    }
+}
+
+// Comments by deleted users and the ones whose IDs are unknown cannot be used as REST responses.
+fn comment_id_and_author(
+    comment: &graphql::batch_lookup::Comment,
+) -> Option<(usize, &graphql::batch_lookup::User)> {
+    let id = comment.full_database_id.as_deref()?.parse().ok()?;
+    Some((id, comment.author.as_ref()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        github::{self, fake::FakeGitHub},
+        testing,
+    };
+    use std::fs;
+
+    #[test]
+    fn cache_users_and_revalidate_by_etag() {
+        let root = testing::temp_dir("cache");
+        fs::create_dir_all(root.join("users")).unwrap();
+        let user_json = |avatar: &str| format!(r#"{{"login":"alice","avatar_url":"{}"}}"#, avatar);
+        fs::write(root.join("users/alice.json"), user_json("a1")).unwrap();
+
+        let fake = FakeGitHub::spawn(root.clone());
+        let client = fake.client("https://github.com");
+        let get_avatar = || {
+            let params = github::GetUserParams { name: "alice" };
+            client.get_user(&params).unwrap().unwrap().avatar_url
+        };
+        let expire = || {
+            let url = format!("{}/users/alice", fake.api_base);
+            let mut cache = client.cache.borrow_mut();
+            let mut entry = cache.get(&url).cloned().unwrap();
+            entry.fetched_at = 0;
+            cache.insert(url, entry);
+        };
+
+        // A fresh entry is used without requests.
+        assert_eq!(get_avatar(), "a1");
+        fs::write(root.join("users/alice.json"), user_json("a2")).unwrap();
+        assert_eq!(get_avatar(), "a1");
 
-        Ok(matches!(
-            updated_state,
-            Some(graphql::SubscriptionState::Unsubscribed)
-        ))
+        // A stale entry is revalidated.
+        expire();
+        assert_eq!(get_avatar(), "a2");
+        expire();
+        assert_eq!(get_avatar(), "a2");
+
+        let statuses = fake.requests().into_iter().map(|(_, status)| status);
+        assert_eq!(statuses.collect::<Vec<_>>(), [200, 200, 304]);
+    }
+
+    #[test]
+    fn use_prefetched_resources_instead_of_rest_requests() {
+        let root = testing::temp_dir("prefetch");
+        let actor = |login: &str| format!(r#"{{"login":"{0}","avatarUrl":"{0}.png"}}"#, login);
+        let data = format!(
+            r#"{{"data":{{"u0":{alice},"u1":null,"i0":{{"issueOrPullRequest":{{
+                "url":"https://github.com/o/r/pull/1","number":1,"title":"Fix","state":"MERGED",
+                "author":{alice},
+                "comments":{{"nodes":[{{"fullDatabaseId":"10","body":"LGTM","author":{bob}}}]}},
+                "reviews":{{"nodes":[
+                    {{"fullDatabaseId":"20","state":"APPROVED","body":"ok","author":{bob},
+                      "comments":{{"nodes":[{{"fullDatabaseId":"30","body":"nit","author":{bob}}}]}}}},
+                    {{"fullDatabaseId":"21","state":"PENDING","body":"","author":{bob},
+                      "comments":{{"nodes":[]}}}}
+                ]}}
+            }},"status":{{"isDraft":false,"reviewDecision":"APPROVED","mergeable":"MERGEABLE",
+                "additions":3,"deletions":1,"labels":{{"nodes":[{{"name":"bug"}}]}},
+                "commits":{{"nodes":[{{"commit":{{"statusCheckRollup":{{"state":"SUCCESS"}}}}}}]}}
+            }}}}}},"errors":[{{"type":"NOT_FOUND","message":"no user"}}]}}"#,
+            alice = actor("alice"),
+            bob = actor("bob"),
+        );
+        fs::write(root.join("graphql.json"), data).unwrap();
+
+        let fake = FakeGitHub::spawn(root);
+        let client = fake.client("https://github.com");
+        let repo = github::Repository {
+            owner: String::from("o"),
+            name: String::from("r"),
+        };
+        let lookups = [
+            github::Lookup::User {
+                login: String::from("alice"),
+            },
+            github::Lookup::User {
+                login: String::from("ghost"),
+            },
+            github::Lookup::IssueComment {
+                repo: repo.clone(),
+                number: 1,
+                comment_id: 10,
+            },
+            github::Lookup::PrReview {
+                repo: repo.clone(),
+                pr_number: 1,
+                review_id: 20,
+            },
+            github::Lookup::PrReviewComment {
+                repo: repo.clone(),
+                pr_number: 1,
+                review_comment_id: 30,
+            },
+            github::Lookup::PrStatus {
+                repo: repo.clone(),
+                number: 1,
+            },
+        ];
+        client.prefetch(&lookups).unwrap();
+
+        let user = client.get_user(&github::GetUserParams { name: "alice" });
+        assert_eq!(user.unwrap().unwrap().avatar_url, "alice.png");
+        let params = github::GetIssueCommentParams {
+            repo: &repo,
+            comment_id: 10,
+        };
+        let comment = client.get_issue_comment(&params).unwrap().unwrap();
+        assert_eq!(
+            (comment.user.login.as_str(), comment.body.as_str()),
+            ("bob", "LGTM")
+        );
+        let params = github::GetPrReviewParams {
+            repo: &repo,
+            pr_number: 1,
+            review_id: 20,
+        };
+        let review = client.get_pr_review(&params).unwrap().unwrap();
+        assert_eq!(review.state, github::ReviewState::Approved);
+        let params = github::GetPrReviewCommentParams {
+            repo: &repo,
+            review_comment_id: 30,
+        };
+        let comment = client.get_pr_review_comment(&params).unwrap().unwrap();
+        assert_eq!(comment.body, "nit");
+        let params = github::GetPrStatusParams {
+            repo: &repo,
+            number: 1,
+        };
+        let status = client.get_pr_status(&params).unwrap().unwrap();
+        assert_eq!(status.checks, Some(github::CheckState::Success));
+        assert_eq!(status.labels, ["bug"]);
+
+        // Another email of the same PR uses the prefetched ones again.
+        let status = client.get_pr_status(&params).unwrap().unwrap();
+        assert_eq!(status.additions, 3);
+        assert_eq!(fake.requests(), [(String::from("/graphql"), 200)]);
+        client.clear_prefetched();
+
+        // The ones not prefetched are fetched by the REST API.
+        let params = github::GetPrReviewParams {
+            repo: &repo,
+            pr_number: 1,
+            review_id: 21,
+        };
+        assert!(client.get_pr_review(&params).unwrap().is_none());
+        let user = client.get_user(&github::GetUserParams { name: "ghost" });
+        assert!(user.unwrap().is_none());
+        let paths = fake.requests().into_iter().map(|(path, _)| path);
+        assert_eq!(
+            paths.collect::<Vec<_>>(),
+            ["/graphql", "/repos/o/r/pulls/1/reviews/21", "/users/ghost"]
+        );
     }
 }
@@ -0,0 +1,156 @@
+//! A cache of GitHub API lookups such as users and issues, which are fetched many times a day.
+//! Fresh entries are used without requests, and stale ones are revalidated by their ETags
+//! since conditional requests answered with 304 do not count against the rate limit.
+
+use crate::store::Store;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+pub const USER_TTL_SECS: u64 = 24 * 60 * 60;
+pub const ISSUE_TTL_SECS: u64 = 10 * 60;
+
+// Enough for the users and issues of a few busy days. Beyond it the least recently
+// fetched entries are evicted, which only costs requests to fetch them again.
+// A tenth of them are evicted at once so that the entries are not sorted on every insert.
+const MAX_ENTRIES: usize = 2000;
+const ENTRIES_AFTER_EVICTION: usize = MAX_ENTRIES * 9 / 10;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheState {
+    // Entries keyed by request URLs.
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub etag: Option<String>,
+    pub fetched_at: u64,
+    // Only the fields we use, to keep the file small.
+    pub value: serde_json::Value,
+}
+
+impl Entry {
+    pub fn is_fresh(&self, now: u64, ttl_secs: u64) -> bool {
+        now < self.fetched_at + ttl_secs
+    }
+}
+
+impl CacheState {
+    fn insert(&mut self, url: String, entry: Entry) {
+        self.entries.insert(url, entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let mut entries = self
+                .entries
+                .iter()
+                .map(|(url, e)| (e.fetched_at, url.as_str()))
+                .collect::<Vec<_>>();
+            entries.sort_unstable();
+            let n_evicted = entries.len() - ENTRIES_AFTER_EVICTION;
+            let evicted = entries[..n_evicted]
+                .iter()
+                .map(|(_, url)| url.to_string())
+                .collect::<Vec<_>>();
+            for url in evicted {
+                self.entries.remove(&url);
+            }
+        }
+    }
+}
+
+/// The cache is kept in memory, and also in a file to survive restarts if loaded from it.
+/// The file is written only by `save`, which is called once per polling cycle.
+#[derive(Debug)]
+pub enum ResponseCache {
+    Memory(CacheState),
+    File {
+        store: Store<CacheState>,
+        dirty: bool,
+    },
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        ResponseCache::Memory(CacheState::default())
+    }
+}
+
+impl ResponseCache {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let store = Store::load(path, || Ok(CacheState::default()))?;
+        Ok(ResponseCache::File {
+            store,
+            dirty: false,
+        })
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Entry> {
+        let state = match self {
+            Self::Memory(state) => state,
+            Self::File { store, .. } => &store.state,
+        };
+        state.entries.get(url)
+    }
+
+    pub fn insert(&mut self, url: String, entry: Entry) {
+        match self {
+            Self::Memory(state) => state.insert(url, entry),
+            Self::File { store, dirty } => {
+                store.state.insert(url, entry);
+                *dirty = true;
+            }
+        }
+    }
+
+    // Write the changes since the last save to the file, if any.
+    pub fn save(&mut self) -> Result<()> {
+        match self {
+            Self::File { store, dirty } if *dirty => {
+                store.save()?;
+                *dirty = false;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn entry(fetched_at: u64) -> Entry {
+        Entry {
+            etag: None,
+            fetched_at,
+            value: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn forget_least_recently_fetched_entries() {
+        let mut cache = ResponseCache::default();
+        for n in 0..(MAX_ENTRIES as u64 + 1) {
+            cache.insert(n.to_string(), entry(n));
+        }
+        let n_evicted = MAX_ENTRIES + 1 - ENTRIES_AFTER_EVICTION;
+        assert!(cache.get(&(n_evicted - 1).to_string()).is_none());
+        assert!(cache.get(&n_evicted.to_string()).is_some());
+        assert!(cache.get(&MAX_ENTRIES.to_string()).is_some());
+    }
+
+    #[test]
+    fn write_file_only_on_save() {
+        let path = testing::temp_dir("cache-file").join("cache.json");
+        let load = || Store::load(path.clone(), || Ok(CacheState::default())).unwrap();
+        let mut cache = ResponseCache::load(path.clone()).unwrap();
+
+        cache.insert(String::from("a"), entry(1));
+        cache.insert(String::from("b"), entry(2));
+        assert!(load().state.entries.is_empty());
+
+        cache.save().unwrap();
+        assert_eq!(load().state.entries.len(), 2);
+    }
+}
@@ -0,0 +1,147 @@
+//! A fake GitHub API server for tests.
+//! It responds to `GET /foo/bar` with the content of `<root>/foo/bar.json`, or 404 if not found.
+//! The query string and the method are ignored. A GraphQL query named like `query PrStatus(...)`
+//! is answered with `<root>/graphql/PrStatus.json` if exists, and the others with `<root>/graphql.json`.
+//! Each response has an ETag of its content, and 304 is returned if `If-None-Match` matches it.
+//! `FakeGitHub::spawn_unauthorized` responds 401 to every request instead.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Debug)]
+pub struct FakeGitHub {
+    pub api_base: String,
+    // The paths and statuses of the handled requests.
+    requests: Arc<Mutex<Vec<(String, u16)>>>,
+}
+
+impl FakeGitHub {
+    pub fn spawn(root: PathBuf) -> FakeGitHub {
+        Self::spawn_with(Some(root))
+    }
+
+    pub fn spawn_unauthorized() -> FakeGitHub {
+        Self::spawn_with(None)
+    }
+
+    fn spawn_with(root: Option<PathBuf>) -> FakeGitHub {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fake GitHub");
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, root.as_deref(), &log);
+            }
+        });
+
+        FakeGitHub { api_base, requests }
+    }
+
+    pub fn requests(&self) -> Vec<(String, u16)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn client(&self, web_base: &str) -> super::Client {
+        let creds = super::Credentials {
+            auth_token: String::from("fake-token"),
+        };
+        let endpoints = super::Endpoints {
+            api_base: self.api_base.clone(),
+            graphql_url: format!("{}/graphql", self.api_base),
+            web_base: web_base.to_string(),
+            extra_web_bases: Vec::new(),
+        };
+        super::Client::new(creds, endpoints).unwrap()
+    }
+}
+
+fn respond(stream: TcpStream, root: Option<&Path>, log: &Mutex<Vec<(String, u16)>>) -> Option<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let path = request_line.split_whitespace().nth(1)?.to_string();
+
+    // Skip the headers and the body.
+    let mut content_len = 0;
+    let mut if_none_match = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_len = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("if-none-match") {
+                if_none_match = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut body = vec![0; content_len];
+    reader.read_exact(&mut body).ok()?;
+
+    let path = path.split('?').next().unwrap_or("").to_string();
+    let (status, content) = match root {
+        None => (401, String::from(r#"{"message":"Bad credentials"}"#)),
+        Some(root) => {
+            let file = fixture_file(root, &path, &body);
+            match fs::read_to_string(&file) {
+                Ok(content) => (200, content),
+                Err(_) => (404, String::from(r#"{"message":"Not Found"}"#)),
+            }
+        }
+    };
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    let (status, content) = match if_none_match {
+        Some(tag) if status == 200 && tag == etag => (304, String::new()),
+        _ => (status, content),
+    };
+
+    let reason = match status {
+        200 => "OK",
+        304 => "Not Modified",
+        401 => "Unauthorized",
+        _ => "Not Found",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        etag,
+        content.len(),
+        content
+    );
+    // Log before responding so that the client always sees its request in the log.
+    log.lock().unwrap().push((path, status));
+    reader.get_mut().write_all(response.as_bytes()).ok()
+}
+
+// The file to respond with. A named GraphQL query has its own file if exists.
+fn fixture_file(root: &Path, path: &str, body: &[u8]) -> PathBuf {
+    let file = root.join(format!("{}.json", path.trim_start_matches('/')));
+    if path != "/graphql" {
+        return file;
+    }
+    let request = serde_json::from_slice::<serde_json::Value>(body).unwrap_or_default();
+    let name = request["query"]
+        .as_str()
+        .and_then(|query| query.trim_start().strip_prefix("query "))
+        .and_then(|query| query.split(|c: char| !c.is_alphanumeric()).next())
+        .unwrap_or("");
+    match root.join(format!("graphql/{}.json", name)) {
+        named if !name.is_empty() && named.exists() => named,
+        _ => file,
+    }
+}
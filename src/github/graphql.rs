@@ -1,6 +1,8 @@
+use crate::web::retry::{self, RetryPolicy, RetryableError};
 use anyhow::{anyhow, Result};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 
 pub trait Query {
     type Output: DeserializeOwned;
@@ -16,32 +18,44 @@ pub struct QueryResult<D> {
 #[derive(Debug, Deserialize)]
 pub struct ErrorItem {
     message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
 }
 
-pub fn send_request<O: DeserializeOwned>(
-    client: &reqwest::blocking::Client,
-    url: &str,
-    query: &impl Query<Output = O>,
-) -> Result<reqwest::blocking::Response> {
-    let res = client.post(url).body(query.to_json().to_string()).send()?;
-    Ok(res)
-}
-
+// Send a query with retries. The GraphQL API may tell rate limits by an error in a 200 response.
 pub fn send<O: DeserializeOwned>(
     client: &reqwest::blocking::Client,
+    retry: &RetryPolicy,
     url: &str,
     query: &impl Query<Output = O>,
 ) -> Result<QueryResult<O>> {
-    let res = send_request(client, url, query)?;
-    Ok(res.json()?)
+    let body = query.to_json().to_string();
+    retry.run(|| {
+        let res = retry::check_status(client.post(url).body(body.clone()).send()?)?;
+        let r: QueryResult<O> = res.json()?;
+        let errors = r.errors.as_deref().unwrap_or_default();
+        if let Some(err) = errors
+            .iter()
+            .find(|e| e.kind.as_deref() == Some("RATE_LIMITED"))
+        {
+            return Err(RetryableError {
+                message: format!("GraphQL rate limited: {}", err.message),
+                retry_after: None,
+                rate_limited: true,
+            }
+            .into());
+        }
+        Ok(r)
+    })
 }
 
 pub fn fetch_data<O: DeserializeOwned>(
     client: &reqwest::blocking::Client,
+    retry: &RetryPolicy,
     url: &str,
     query: &impl Query<Output = O>,
 ) -> Result<O> {
-    let r = send(client, url, query)?;
+    let r = send(client, retry, url, query)?;
     if let Some(errs) = r.errors {
         let msg = errs
             .into_iter()
@@ -52,7 +66,7 @@ pub fn fetch_data<O: DeserializeOwned>(
     }
     match r.data {
         Some(data) => Ok(data),
-        None => panic!("GraphQL response does not contain data nor errors"),
+        None => Err(anyhow!("GraphQL response contains neither data nor errors")),
     }
 }
 
@@ -148,6 +162,145 @@ pub mod get_review_requests {
     }
 }
 
+// The fields of a PR for `pr_status::PullRequest`, shared with `BatchLookupQuery`.
+const PR_STATUS_FIELDS: &str = r#"
+    isDraft
+    reviewDecision
+    mergeable
+    additions
+    deletions
+    labels(first: 20) {
+      nodes {
+        name
+      }
+    }
+    commits(last: 1) {
+      nodes {
+        commit {
+          statusCheckRollup {
+            state
+          }
+        }
+      }
+    }
+"#;
+
+#[derive(Debug)]
+pub struct PrStatusQuery<'a> {
+    pub owner: &'a str,
+    pub repo: &'a str,
+    pub pr_number: usize,
+}
+
+impl Query for PrStatusQuery<'_> {
+    type Output = pr_status::Payload;
+
+    fn to_json(&self) -> serde_json::Value {
+        let query = format!(
+            r#"
+            query PrStatus($owner: String!, $repo: String!, $pr_number: Int!) {{
+              repository(owner: $owner, name: $repo) {{
+                pullRequest(number: $pr_number) {{ {} }}
+              }}
+            }}
+            "#,
+            PR_STATUS_FIELDS
+        );
+        json!({
+            "query": query,
+            "variables": {
+                "owner": self.owner,
+                "repo": self.repo,
+                "pr_number": self.pr_number,
+            },
+        })
+    }
+}
+
+pub mod pr_status {
+    use crate::github;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct Payload {
+        pub repository: Option<Repository>,
+    }
+
+    impl Payload {
+        pub fn into_pull_request(self) -> Option<PullRequest> {
+            self.repository?.pull_request
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Repository {
+        pub pull_request: Option<PullRequest>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct PullRequest {
+        pub is_draft: bool,
+        pub review_decision: Option<github::ReviewDecision>,
+        pub mergeable: github::MergeableState,
+        pub additions: usize,
+        pub deletions: usize,
+        pub labels: Option<Connection<Label>>,
+        pub commits: Connection<CommitNode>,
+    }
+
+    impl PullRequest {
+        pub fn into_status(self) -> github::PrStatus {
+            let checks = self
+                .commits
+                .nodes
+                .into_iter()
+                .last()
+                .and_then(|node| node.commit.status_check_rollup)
+                .map(|rollup| rollup.state);
+            let labels = self.labels.map_or(Vec::new(), |labels| {
+                labels.nodes.into_iter().map(|label| label.name).collect()
+            });
+            github::PrStatus {
+                is_draft: self.is_draft,
+                checks,
+                review_decision: self.review_decision,
+                mergeable: self.mergeable,
+                labels,
+                additions: self.additions,
+                deletions: self.deletions,
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Connection<T> {
+        pub nodes: Vec<T>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Label {
+        pub name: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct CommitNode {
+        pub commit: Commit,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Commit {
+        pub status_check_rollup: Option<StatusCheckRollup>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct StatusCheckRollup {
+        pub state: github::CheckState,
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateSubscriptionInput {
@@ -155,7 +308,7 @@ pub struct UpdateSubscriptionInput {
     pub subscribable_id: String,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum SubscriptionState {
     Ignored,
@@ -218,3 +371,162 @@ pub mod update_subscription_mut {
         pub viewer_subscription: Option<super::SubscriptionState>,
     }
 }
+
+/// Looks up users and issues (or PRs) with their comments and reviews at once, by aliases
+/// such as `u0` and `i0`. The aliases not found are null in the response.
+#[derive(Debug, Default)]
+pub struct BatchLookupQuery<'a> {
+    pub users: Vec<&'a str>,
+    pub issues: Vec<IssueLookup<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IssueLookup<'a> {
+    pub owner: &'a str,
+    pub repo: &'a str,
+    pub number: usize,
+    pub comments: bool,
+    pub reviews: bool,
+    pub status: bool,
+}
+
+impl Query for BatchLookupQuery<'_> {
+    type Output = HashMap<String, serde_json::Value>;
+
+    fn to_json(&self) -> serde_json::Value {
+        let comments = |range: &str| {
+            format!(
+                "comments({}) {{ nodes {{ fullDatabaseId body author {{ ...actor }} }} }}",
+                range
+            )
+        };
+        let mut params = Vec::new();
+        let mut fields = Vec::new();
+        let mut variables = serde_json::Map::new();
+
+        for (i, login) in self.users.iter().enumerate() {
+            params.push(format!("$u{}: String!", i));
+            fields.push(format!("u{0}: user(login: $u{0}) {{ ...actor }}", i));
+            variables.insert(format!("u{}", i), json!(login));
+        }
+        for (i, issue) in self.issues.iter().enumerate() {
+            params.push(format!("$o{0}: String!, $r{0}: String!, $n{0}: Int!", i));
+            let mut issue_fields = String::from("url number title state author { ...actor }");
+            if issue.comments {
+                issue_fields += &format!(" {}", comments("last: 50"));
+            }
+            let mut pr_fields = issue_fields.clone();
+            if issue.reviews {
+                pr_fields += &format!(
+                    " reviews(last: 30) {{ nodes {{ fullDatabaseId state body author {{ ...actor }} {} }} }}",
+                    comments("first: 30")
+                );
+            }
+            // Alias the PR itself to deserialize its status in the same way as `PrStatusQuery`.
+            let status_field = if issue.status {
+                format!(
+                    "status: pullRequest(number: $n{}) {{ {} }}",
+                    i, PR_STATUS_FIELDS
+                )
+            } else {
+                String::new()
+            };
+            fields.push(format!(
+                "i{0}: repository(owner: $o{0}, name: $r{0}) {{ issueOrPullRequest(number: $n{0}) {{ \
+                 ... on Issue {{ {1} }} ... on PullRequest {{ {2} }} }} {3} }}",
+                i, issue_fields, pr_fields, status_field
+            ));
+            variables.insert(format!("o{}", i), json!(issue.owner));
+            variables.insert(format!("r{}", i), json!(issue.repo));
+            variables.insert(format!("n{}", i), json!(issue.number));
+        }
+
+        let query = format!(
+            "query({}) {{ {} }} fragment actor on Actor {{ login avatarUrl }}",
+            params.join(", "),
+            fields.join(" ")
+        );
+        json!({ "query": query, "variables": variables })
+    }
+}
+
+pub mod batch_lookup {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct User {
+        pub login: String,
+        pub avatar_url: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Repository {
+        pub issue_or_pull_request: Option<Issue>,
+        pub status: Option<super::pr_status::PullRequest>,
+    }
+
+    // An issue or a PR.
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Issue {
+        pub url: String,
+        pub number: usize,
+        pub title: String,
+        // OPEN, CLOSED or MERGED.
+        pub state: String,
+        // Null if the user is deleted.
+        pub author: Option<User>,
+        pub comments: Option<Connection<Comment>>,
+        pub reviews: Option<Connection<Review>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Connection<T> {
+        pub nodes: Vec<Option<T>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Comment {
+        // The REST API ID. It is a string since it may exceed the `Int` of GraphQL.
+        pub full_database_id: Option<String>,
+        pub body: String,
+        pub author: Option<User>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Review {
+        pub full_database_id: Option<String>,
+        pub state: String,
+        pub body: String,
+        pub author: Option<User>,
+        pub comments: Option<Connection<Comment>>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{github::fake::FakeGitHub, testing};
+    use std::fs;
+
+    #[test]
+    fn fail_without_data_nor_errors() {
+        let root = testing::temp_dir("graphql");
+        fs::write(root.join("graphql.json"), r#"{"data":null}"#).unwrap();
+        let fake = FakeGitHub::spawn(root);
+
+        let query = GetReviewRequestsQuery {
+            owner: "acme",
+            repo: "widgets",
+            pr_number: 1,
+        };
+        let client = reqwest::blocking::Client::new();
+        let url = format!("{}/graphql", fake.api_base);
+        let err = fetch_data(&client, &RetryPolicy::default(), &url, &query).unwrap_err();
+        assert!(err.to_string().contains("neither data nor errors"));
+    }
+}
@@ -0,0 +1,130 @@
+mod session;
+
+use crate::email::{self, Email};
+use anyhow::Result;
+use session::{Session, Stream};
+use std::{net::TcpStream, time::Duration};
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug)]
+pub struct Server {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+#[derive(Debug)]
+pub struct Client {
+    server: Server,
+    creds: Credentials,
+}
+
+#[derive(Debug)]
+pub struct FetchParams<'a> {
+    pub folder: &'a str,
+    pub last_uid: Option<u32>,
+    // The UIDVALIDITY of the folder when `last_uid` was saved, if known.
+    pub uid_validity: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct FetchResponse {
+    pub messages: Vec<Message>,
+    pub last_uid: u32,
+    pub uid_validity: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub uid: u32,
+    pub raw: Vec<u8>,
+}
+
+impl Client {
+    pub fn new(server: Server, creds: Credentials) -> Self {
+        Client { server, creds }
+    }
+
+    fn connect(&self) -> Result<Session<Box<dyn Stream>>> {
+        let addr = (self.server.host.as_str(), self.server.port);
+        let tcp = TcpStream::connect(addr)?;
+        tcp.set_read_timeout(Some(Duration::from_secs(60)))?;
+        let stream: Box<dyn Stream> = if self.server.tls {
+            let connector = native_tls::TlsConnector::new()?;
+            Box::new(connector.connect(&self.server.host, tcp)?)
+        } else {
+            Box::new(tcp)
+        };
+        Session::new(stream)
+    }
+
+    /// Fetches unseen messages whose UID is greater than `last_uid`.
+    /// If `last_uid` is not given, this fetches nothing and just returns the current last UID
+    /// so that we start from new messages like the Slack channel polling does.
+    /// The same applies when the UIDVALIDITY of the folder has changed, since the old UIDs
+    /// no longer identify the messages.
+    pub fn fetch_unseen(&self, params: FetchParams) -> Result<FetchResponse> {
+        let mut session = self.connect()?;
+        session.login(&self.creds.username, &self.creds.password)?;
+        let mailbox = session.select(params.folder)?;
+
+        let last_uid = match (params.last_uid, params.uid_validity) {
+            (Some(_), Some(validity)) if mailbox.uid_validity != Some(validity) => {
+                log::warn!(
+                    "UIDVALIDITY of {} changed from {} to {:?}, so the unprocessed messages are skipped",
+                    params.folder,
+                    validity,
+                    mailbox.uid_validity
+                );
+                None
+            }
+            (last_uid, _) => last_uid,
+        };
+
+        let res = match last_uid {
+            None => FetchResponse {
+                messages: Vec::new(),
+                last_uid: mailbox.uid_next.unwrap_or(1).saturating_sub(1),
+                uid_validity: mailbox.uid_validity,
+            },
+            Some(last_uid) => {
+                // Note that `N:*` always contains the last message even if its UID is less than N.
+                let criteria = format!("UID {}:* UNSEEN", last_uid + 1);
+                let uids = session
+                    .uid_search(&criteria)?
+                    .into_iter()
+                    .filter(|uid| *uid > last_uid)
+                    .collect::<Vec<_>>();
+                let messages = session
+                    .uid_fetch_bodies(&uids)?
+                    .into_iter()
+                    .map(|(uid, raw)| Message { uid, raw })
+                    .collect::<Vec<_>>();
+                let last_uid = messages.iter().map(|m| m.uid).max().unwrap_or(last_uid);
+                FetchResponse {
+                    messages,
+                    last_uid,
+                    uid_validity: mailbox.uid_validity,
+                }
+            }
+        };
+
+        session.logout()?;
+        Ok(res)
+    }
+}
+
+pub fn extract_email_from_message(msg: Message) -> Option<Email> {
+    match email::parse_message(&msg.raw) {
+        Ok(email) => Some(email),
+        Err(err) => {
+            log::warn!("failed to parse IMAP message (UID {}): {}", msg.uid, err);
+            None
+        }
+    }
+}
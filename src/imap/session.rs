@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// A minimal IMAP4rev1 session which supports only the commands Gharry needs.
+pub struct Session<S: Read + Write> {
+    stream: BufReader<S>,
+    tag_seq: usize,
+}
+
+// An untagged response line. Literals (`{N}\r\n...`) in the line are collected separately.
+#[derive(Debug)]
+struct Response {
+    line: String,
+    literals: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct Mailbox {
+    pub uid_next: Option<u32>,
+    // UIDs are valid only while this value stays the same.
+    pub uid_validity: Option<u32>,
+}
+
+impl<S: Read + Write> Session<S> {
+    pub fn new(stream: S) -> Result<Self> {
+        let mut session = Session {
+            stream: BufReader::new(stream),
+            tag_seq: 0,
+        };
+        let greeting = session.read_response()?;
+        if !greeting.line.starts_with("* OK") {
+            return Err(anyhow!("unexpected IMAP greeting: {}", greeting.line));
+        }
+        Ok(session)
+    }
+
+    pub fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        let cmd = format!("LOGIN {} {}", quote(username), quote(password));
+        self.command(&cmd).context("failed to login")?;
+        Ok(())
+    }
+
+    pub fn select(&mut self, folder: &str) -> Result<Mailbox> {
+        let responses = self.command(&format!("SELECT {}", quote(folder)))?;
+        let find_code = |name: &str| -> Option<u32> {
+            let re = regex!(r"\[(?P<name>[A-Z-]+) (?P<value>\d+)\]");
+            responses
+                .iter()
+                .flat_map(|r| re.captures_iter(&r.line))
+                .find(|caps| &caps["name"] == name)
+                .and_then(|caps| caps["value"].parse().ok())
+        };
+        Ok(Mailbox {
+            uid_next: find_code("UIDNEXT"),
+            uid_validity: find_code("UIDVALIDITY"),
+        })
+    }
+
+    pub fn uid_search(&mut self, criteria: &str) -> Result<Vec<u32>> {
+        let responses = self.command(&format!("UID SEARCH {}", criteria))?;
+        let uids = responses
+            .iter()
+            .filter_map(|r| r.line.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace())
+            .filter_map(|uid| uid.parse().ok())
+            .collect();
+        Ok(uids)
+    }
+
+    /// Fetches whole messages without setting `\Seen` flags.
+    pub fn uid_fetch_bodies(&mut self, uids: &[u32]) -> Result<Vec<(u32, Vec<u8>)>> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let uid_set = uids
+            .iter()
+            .map(|uid| uid.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let responses = self.command(&format!("UID FETCH {} (UID BODY.PEEK[])", uid_set))?;
+        let re = regex!(r"^\* \d+ FETCH \(.*UID (?P<uid>\d+)");
+        let mut bodies = responses
+            .into_iter()
+            .filter_map(|mut r| {
+                let uid = re.captures(&r.line)?["uid"].parse().ok()?;
+                let body = r.literals.pop()?;
+                Some((uid, body))
+            })
+            .collect::<Vec<_>>();
+        bodies.sort_by_key(|(uid, _)| *uid);
+        Ok(bodies)
+    }
+
+    pub fn logout(&mut self) -> Result<()> {
+        self.command("LOGOUT")?;
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: &str) -> Result<Vec<Response>> {
+        self.tag_seq += 1;
+        let tag = format!("a{}", self.tag_seq);
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{} {}\r\n", tag, cmd).as_bytes())?;
+        stream.flush()?;
+
+        let tag_prefix = format!("{} ", tag);
+        let mut responses = Vec::new();
+        loop {
+            let res = self.read_response()?;
+            match res.line.strip_prefix(&tag_prefix) {
+                Some(status) if status.starts_with("OK") => return Ok(responses),
+                Some(status) => {
+                    let name = cmd.split(' ').next().unwrap_or(cmd);
+                    return Err(anyhow!("IMAP {} failed: {}", name, status));
+                }
+                None => responses.push(res),
+            }
+        }
+    }
+
+    fn read_response(&mut self) -> Result<Response> {
+        let literal_re = regex!(r"\{(?P<len>\d+)\}$");
+        let mut line = String::new();
+        let mut literals = Vec::new();
+        loop {
+            let mut buf = Vec::new();
+            if self.stream.read_until(b'\n', &mut buf)? == 0 {
+                return Err(anyhow!("IMAP connection closed unexpectedly"));
+            }
+            let chunk = String::from_utf8_lossy(&buf);
+            let chunk = chunk.trim_end_matches(&['\r', '\n'][..]);
+            line.push_str(chunk);
+
+            let len = match literal_re.captures(chunk) {
+                None => break,
+                Some(caps) => caps["len"].parse::<usize>()?,
+            };
+            let mut literal = vec![0; len];
+            self.stream.read_exact(&mut literal)?;
+            literals.push(literal);
+        }
+        Ok(Response { line, literals })
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A stand-in of an IMAP server which replies the scripted responses in order.
+    struct Script {
+        responses: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(responses: &[&str]) -> Self {
+            Script {
+                responses: Cursor::new(responses.concat().into_bytes()),
+                written: Vec::new(),
+            }
+        }
+
+        fn commands(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.written)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fetch_response(seq: usize, uid: u32, body: &str) -> String {
+        format!(
+            "* {} FETCH (UID {} BODY[] {{{}}}\r\n{})\r\n",
+            seq,
+            uid,
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn fetch_unseen_messages() {
+        let body = "Subject: Hello\r\n\r\nHi\r\n";
+        let mut script = Script::new(&[
+            "* OK IMAP4rev1 ready\r\n",
+            "a1 OK LOGIN completed\r\n",
+            "* 3 EXISTS\r\n",
+            "* OK [UIDVALIDITY 1700000000] UIDs valid\r\n",
+            "* OK [UIDNEXT 12] Predicted next UID\r\n",
+            "a2 OK [READ-WRITE] SELECT completed\r\n",
+            "* SEARCH 10 11\r\n",
+            "a3 OK SEARCH completed\r\n",
+            &fetch_response(2, 11, body),
+            &fetch_response(1, 10, body),
+            "a4 OK FETCH completed\r\n",
+            "* BYE Logging out\r\n",
+            "a5 OK LOGOUT completed\r\n",
+        ]);
+
+        let mut session = Session::new(&mut script).unwrap();
+        session.login("ryym", "pass\"word").unwrap();
+        let mailbox = session.select("INBOX").unwrap();
+        assert_eq!(mailbox.uid_next, Some(12));
+        assert_eq!(mailbox.uid_validity, Some(1700000000));
+        let uids = session.uid_search("UID 10:* UNSEEN").unwrap();
+        assert_eq!(uids, [10, 11]);
+        let bodies = session.uid_fetch_bodies(&uids).unwrap();
+        let uids = bodies.iter().map(|(uid, _)| *uid).collect::<Vec<_>>();
+        assert_eq!(uids, [10, 11]);
+        assert_eq!(bodies[0].1, body.as_bytes());
+        session.logout().unwrap();
+        drop(session);
+
+        assert_eq!(
+            script.commands(),
+            [
+                r#"a1 LOGIN "ryym" "pass\"word""#,
+                r#"a2 SELECT "INBOX""#,
+                "a3 UID SEARCH UID 10:* UNSEEN",
+                "a4 UID FETCH 10,11 (UID BODY.PEEK[])",
+                "a5 LOGOUT",
+            ]
+        );
+    }
+
+    #[test]
+    fn fail_on_rejected_command() {
+        let mut script = Script::new(&[
+            "* OK IMAP4rev1 ready\r\n",
+            "a1 NO [AUTHENTICATIONFAILED] Invalid credentials\r\n",
+        ]);
+        let mut session = Session::new(&mut script).unwrap();
+        let err = session.login("ryym", "wrong").unwrap_err();
+        assert!(format!("{:#}", err).contains("IMAP LOGIN failed: NO"));
+    }
+}
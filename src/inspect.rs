@@ -1,20 +1,38 @@
-use crate::{config::Config, github, notif, notifier, slack};
-use anyhow::Result;
+use crate::{
+    config::{Config, SourceConfig},
+    github, notif, notifier, slack,
+};
+use anyhow::{anyhow, Result};
 use std::fs::OpenOptions;
 use std::io::Write;
 
 pub fn run(config: &Config, oldest_ts: &str, limit: Option<&str>, send_notifs: bool) -> Result<()> {
-    let slack = slack::Client::new(slack::Credentials {
-        bot_token: config.slack.bot_token.clone(),
-    })?;
-    let github = github::Client::new(github::Credentials {
-        auth_token: config.github.auth_token.clone(),
-    })?;
+    let slack = slack::Client::new(
+        slack::Credentials {
+            bot_token: config.slack.bot_token.clone(),
+        },
+        &config.slack.api_base,
+    )?;
+    let github = github::Client::new(
+        github::Credentials {
+            auth_token: config.github.auth_token.clone(),
+        },
+        config.github.endpoints.clone(),
+    )?;
 
+    let mail_channel_id = config
+        .sources
+        .iter()
+        .find_map(|s| match s {
+            SourceConfig::SlackChannel { channel_id } => Some(channel_id),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("no Slack mail channel configured"))?;
     let data = slack.conversations_history(slack::ConvHistoryParams {
-        channel: &config.slack.mail_channel_id,
+        channel: mail_channel_id,
         oldest_ts,
         limit: limit.or(Some("1")),
+        cursor: None,
     })?;
 
     let msgs_json = serde_json::to_string(&data.messages)?;
@@ -26,7 +44,12 @@ pub fn run(config: &Config, oldest_ts: &str, limit: Option<&str>, send_notifs: b
         .write_all(&msgs_json.into_bytes())?;
 
     let ctx = notif::BuildContext { github: &github };
-    let notifs = notif::build_notifications(ctx, data.messages)?;
+    let emails = data
+        .messages
+        .into_iter()
+        .filter_map(slack::extract_email_from_message)
+        .collect();
+    let notifs = notif::build_notifications(ctx, emails)?;
 
     let notifs_json = serde_json::to_string(&notifs)?;
     OpenOptions::new()
@@ -38,7 +61,8 @@ pub fn run(config: &Config, oldest_ts: &str, limit: Option<&str>, send_notifs: b
 
     if send_notifs {
         for notif in notifs {
-            notifier::notify_by_slack(&slack, &config.slack.dest_channel_id, notif)?;
+            let decision = config.rules.decide(&notif);
+            notifier::notify_by_slack(&slack, config, &decision, &notif, None)?;
         }
     }
 
@@ -0,0 +1,278 @@
+//! Handles the buttons on Slack messages via the Slack interactivity endpoint.
+//! See https://api.slack.com/interactivity/handling for the payloads.
+
+use crate::{
+    config::{Config, InteractivityConfig},
+    digest::now,
+    github, slack,
+    snooze::{self, SnoozeState},
+    store::Store,
+    web::server::{self, Request, Response},
+};
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{net::TcpListener, sync::mpsc, thread};
+
+// The action IDs of the buttons which call back into Gharry.
+pub const ACTION_MUTE_PR: &str = "mute_pr";
+pub const ACTION_IGNORE_PR: &str = "ignore_pr";
+pub const ACTION_SNOOZE_PR: &str = "snooze_pr";
+
+// The path of the Request URL set in the Slack app.
+const PATH: &str = "/";
+
+// Requests older than this are rejected to prevent replay attacks.
+const MAX_REQUEST_AGE_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Deserialize)]
+struct RawForm {
+    payload: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Payload {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    actions: Vec<PayloadAction>,
+    response_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayloadAction {
+    action_id: String,
+    value: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Interactions {
+    profile: String,
+    signing_secret: String,
+    // Slack expects a reply within 3 seconds, so actions run on a worker after the reply.
+    actions: mpsc::Sender<Payload>,
+}
+
+#[derive(Debug)]
+struct Actions {
+    profile: String,
+    snooze_secs: u64,
+    github: github::Client,
+    slack: slack::Client,
+    snoozes: Store<SnoozeState>,
+}
+
+// Start an interactivity server for each profile that enables it.
+pub fn spawn_servers(configs: &[Config]) -> Result<()> {
+    for config in configs {
+        let interactivity = match &config.interactivity {
+            Some(interactivity) => interactivity,
+            None => continue,
+        };
+        let listener = TcpListener::bind(&interactivity.listen)
+            .with_context(|| format!("failed to listen on {}", interactivity.listen))?;
+        let mut interactions = Interactions::new(config, interactivity)?;
+        log::info!(
+            "[{}] Listening Slack interactions on {}",
+            config.profile,
+            interactivity.listen
+        );
+        thread::spawn(move || server::serve(listener, |req| interactions.handle(req)));
+    }
+    Ok(())
+}
+
+impl Interactions {
+    pub fn new(config: &Config, interactivity: &InteractivityConfig) -> Result<Self> {
+        let github = github::Client::new(
+            github::Credentials {
+                auth_token: config.github.auth_token.clone(),
+            },
+            config.github.endpoints.clone(),
+        )?;
+        let slack = slack::Client::new(
+            slack::Credentials {
+                bot_token: config.slack.bot_token.clone(),
+            },
+            &config.slack.api_base,
+        )?;
+        let snoozes = Store::load(snooze::store_path(config)?, || Ok(SnoozeState::default()))?;
+        let actions = Actions {
+            profile: config.profile.clone(),
+            snooze_secs: interactivity.snooze_hours * 60 * 60,
+            github,
+            slack,
+            snoozes,
+        };
+        Ok(Self::spawn(
+            config.profile.clone(),
+            interactivity.signing_secret.clone(),
+            actions,
+        ))
+    }
+
+    // Start a worker which runs the actions of accepted requests one by one.
+    fn spawn(profile: String, signing_secret: String, mut actions: Actions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for payload in receiver {
+                if let Err(err) = actions.dispatch(&payload) {
+                    log::error!(
+                        "[{}] Failed to handle interaction: {:#}",
+                        actions.profile,
+                        err
+                    );
+                }
+            }
+        });
+        Interactions {
+            profile,
+            signing_secret,
+            actions: sender,
+        }
+    }
+
+    pub fn handle(&mut self, req: &Request) -> Response {
+        if req.method != "POST" || req.route() != PATH {
+            return Response::error(404, "not found");
+        }
+        if !verify_signature(&self.signing_secret, req, now()) {
+            log::warn!("[{}] Reject request with invalid signature", self.profile);
+            return Response::error(401, "invalid signature");
+        }
+        let payload = match parse_payload(&req.body) {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::warn!("[{}] Reject invalid interaction: {:#}", self.profile, err);
+                return Response::error(400, "invalid payload");
+            }
+        };
+        if payload.kind != "block_actions" {
+            log::debug!("ignore interaction: {}", payload.kind);
+            return Response::ok();
+        }
+        match self.actions.send(payload) {
+            Ok(()) => Response::ok(),
+            Err(_) => {
+                log::error!("[{}] The interaction worker has stopped", self.profile);
+                Response::error(500, "failed to handle interaction")
+            }
+        }
+    }
+}
+
+impl Actions {
+    fn dispatch(&mut self, payload: &Payload) -> Result<()> {
+        for action in &payload.actions {
+            let reply = match self.run_action(action)? {
+                Some(reply) => reply,
+                None => continue,
+            };
+            log::info!("[{}] {}", self.profile, reply);
+            if let Some(response_url) = &payload.response_url {
+                self.slack.respond(
+                    response_url,
+                    &slack::ResponseMessage {
+                        text: &reply,
+                        response_type: "ephemeral",
+                        replace_original: false,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Run an action and return a reply message. Link buttons are ignored.
+    fn run_action(&mut self, action: &PayloadAction) -> Result<Option<String>> {
+        let state = match action.action_id.as_str() {
+            ACTION_MUTE_PR => github::SubscriptionState::Unsubscribed,
+            ACTION_IGNORE_PR => github::SubscriptionState::Ignored,
+            ACTION_SNOOZE_PR => {
+                let (repo, number) = parse_target(action.value.as_deref())?;
+                let now = now();
+                let until = now + self.snooze_secs;
+                self.snoozes.update_state_with(|state| {
+                    state.snooze(&repo.fullname(), number, until, now);
+                })?;
+                let hours = self.snooze_secs / 60 / 60;
+                return Ok(Some(format!(
+                    "Snoozed {}#{} for {} hours",
+                    repo.fullname(),
+                    number,
+                    hours
+                )));
+            }
+            _ => return Ok(None),
+        };
+
+        let (repo, number) = parse_target(action.value.as_deref())?;
+        let done = self
+            .github
+            .update_pr_subscription(&github::UpdatePrSubscriptionParams {
+                repo: &repo,
+                number,
+                state,
+            })?;
+        let verb = match state {
+            github::SubscriptionState::Ignored => "ignore",
+            _ => "unsubscribe",
+        };
+        let reply = if done {
+            format!("Succeeded to {} {}#{}", verb, repo.fullname(), number)
+        } else {
+            format!("Failed to {} {}#{}", verb, repo.fullname(), number)
+        };
+        Ok(Some(reply))
+    }
+}
+
+fn parse_payload(body: &[u8]) -> Result<Payload> {
+    let form: RawForm = serde_urlencoded::from_bytes(body).context("invalid form body")?;
+    serde_json::from_str(&form.payload).context("invalid payload")
+}
+
+// Parse a button value in the form of `owner/name#number`.
+fn parse_target(value: Option<&str>) -> Result<(github::Repository, usize)> {
+    let value = value.ok_or_else(|| anyhow!("no value in action"))?;
+    let parsed = value.split_once('#').and_then(|(fullname, number)| {
+        let (owner, name) = fullname.split_once('/')?;
+        let repo = github::Repository {
+            owner: owner.to_string(),
+            name: name.to_string(),
+        };
+        Some((repo, number.parse().ok()?))
+    });
+    parsed.ok_or_else(|| anyhow!("invalid action value: {}", value))
+}
+
+// Verify a request is sent from Slack.
+// See https://api.slack.com/authentication/verifying-requests-from-slack.
+fn verify_signature(signing_secret: &str, req: &Request, now: u64) -> bool {
+    let (timestamp, signature) = match (
+        req.header("X-Slack-Request-Timestamp"),
+        req.header("X-Slack-Signature"),
+    ) {
+        (Some(timestamp), Some(signature)) => (timestamp, signature),
+        _ => return false,
+    };
+    match timestamp.parse::<u64>() {
+        Ok(ts) if now.abs_diff(ts) <= MAX_REQUEST_AGE_SECS => {}
+        _ => return false,
+    }
+    let signature = match signature.strip_prefix("v0=").map(hex::decode) {
+        Some(Ok(signature)) => signature,
+        _ => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_from_slice(signing_secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(&req.body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests;
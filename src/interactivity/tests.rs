@@ -0,0 +1,171 @@
+use super::*;
+use crate::{github::fake::FakeGitHub, testing};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+
+struct TestServer {
+    url: String,
+    dir: PathBuf,
+    github: FakeGitHub,
+}
+
+fn spawn_server(name: &str) -> TestServer {
+    let dir = testing::temp_dir(name);
+    let fake = testing::fake_github();
+    let actions = Actions {
+        profile: String::from("test"),
+        snooze_secs: 60 * 60,
+        github: fake.client("https://github.com"),
+        slack: slack::Client::new(
+            slack::Credentials {
+                bot_token: String::from("xoxb-test"),
+            },
+            &fake.api_base,
+        )
+        .unwrap(),
+        snoozes: Store::load(dir.join("snoozes.json"), || Ok(SnoozeState::default())).unwrap(),
+    };
+    let mut interactions = Interactions::spawn(String::from("test"), String::from(SECRET), actions);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || server::serve(listener, |req| interactions.handle(req)));
+    TestServer {
+        url,
+        dir,
+        github: fake,
+    }
+}
+
+// Receive the replies posted to a response URL.
+fn spawn_response_url() -> (String, mpsc::Receiver<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        server::serve(listener, |req| {
+            sender
+                .send(serde_json::from_slice(&req.body).unwrap())
+                .unwrap();
+            Response::ok()
+        })
+    });
+    (url, receiver)
+}
+
+fn action_body(action_id: &str, value: &str) -> String {
+    action_body_with_response(action_id, value, None)
+}
+
+fn action_body_with_response(action_id: &str, value: &str, response_url: Option<&str>) -> String {
+    let payload = serde_json::json!({
+        "type": "block_actions",
+        "actions": [{ "action_id": action_id, "value": value }],
+        "response_url": response_url,
+    });
+    serde_urlencoded::to_string([("payload", payload.to_string())]).unwrap()
+}
+
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+    format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn post(url: &str, body: String, timestamp: u64, signature: &str) -> u16 {
+    reqwest::blocking::Client::new()
+        .post(url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Slack-Request-Timestamp", timestamp.to_string())
+        .header("X-Slack-Signature", signature)
+        .body(body)
+        .send()
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+fn load_snoozes(dir: &std::path::Path) -> SnoozeState {
+    Store::load(dir.join("snoozes.json"), || Ok(SnoozeState::default()))
+        .unwrap()
+        .state
+}
+
+#[test]
+fn snooze_pr_by_signed_request() {
+    let server = spawn_server("snooze");
+    let (response_url, replies) = spawn_response_url();
+    let body = action_body_with_response(ACTION_SNOOZE_PR, "acme/widgets#42", Some(&response_url));
+    let signature = sign(SECRET, now(), &body);
+
+    assert_eq!(post(&server.url, body, now(), &signature), 200);
+
+    let reply = replies.recv().unwrap();
+    assert_eq!(reply["text"], "Snoozed acme/widgets#42 for 1 hours");
+    let snoozes = load_snoozes(&server.dir);
+    assert_eq!(snoozes.snoozes.len(), 1);
+    assert_eq!(snoozes.snoozes[0].repo, "acme/widgets");
+    assert_eq!(snoozes.snoozes[0].number, 42);
+}
+
+#[test]
+fn mute_pr_by_signed_request() {
+    let server = spawn_server("mute");
+    let (response_url, replies) = spawn_response_url();
+    let body = action_body_with_response(ACTION_MUTE_PR, "acme/widgets#42", Some(&response_url));
+    let signature = sign(SECRET, now(), &body);
+
+    assert_eq!(post(&server.url, body, now(), &signature), 200);
+
+    let reply = replies.recv().unwrap();
+    assert_eq!(reply["text"], "Succeeded to unsubscribe acme/widgets#42");
+    // The PR is looked up and then unsubscribed, which the fake confirms as `UNSUBSCRIBED`.
+    let requests = server.github.requests();
+    let paths = requests.iter().map(|(path, _)| path.as_str());
+    assert_eq!(paths.collect::<Vec<_>>(), ["/graphql", "/graphql"]);
+}
+
+#[test]
+fn reply_before_running_actions() {
+    let server = spawn_server("reply-first");
+    // A response URL which never responds would block the reply if actions ran before it.
+    let stalled = TcpListener::bind("127.0.0.1:0").unwrap();
+    let response_url = format!("http://{}/", stalled.local_addr().unwrap());
+    let body = action_body_with_response(ACTION_MUTE_PR, "acme/widgets#42", Some(&response_url));
+    let signature = sign(SECRET, now(), &body);
+
+    assert_eq!(post(&server.url, body, now(), &signature), 200);
+}
+
+#[test]
+fn reject_unknown_path() {
+    let server = spawn_server("unknown-path");
+    let body = action_body(ACTION_SNOOZE_PR, "acme/widgets#42");
+    let signature = sign(SECRET, now(), &body);
+    let url = format!("{}slack/other", server.url);
+
+    assert_eq!(post(&url, body, now(), &signature), 404);
+    assert!(load_snoozes(&server.dir).snoozes.is_empty());
+}
+
+#[test]
+fn reject_invalid_signature() {
+    let server = spawn_server("invalid-signature");
+    let body = action_body(ACTION_SNOOZE_PR, "acme/widgets#42");
+    let signature = sign("wrong-secret", now(), &body);
+
+    assert_eq!(post(&server.url, body, now(), &signature), 401);
+    assert!(load_snoozes(&server.dir).snoozes.is_empty());
+}
+
+#[test]
+fn reject_stale_request() {
+    let server = spawn_server("stale");
+    let body = action_body(ACTION_SNOOZE_PR, "acme/widgets#42");
+    let timestamp = now() - MAX_REQUEST_AGE_SECS - 1;
+    let signature = sign(SECRET, timestamp, &body);
+
+    assert_eq!(post(&server.url, body, timestamp, &signature), 401);
+}
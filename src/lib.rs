@@ -1,11 +1,33 @@
+// Compile a regex literal only once, on its first use.
+macro_rules! regex {
+    ($re:literal $(,)?) => {{
+        static RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+        RE.get_or_init(|| regex::Regex::new($re).unwrap())
+    }};
+}
+
 pub mod config;
+mod digest;
+pub mod dlq;
 mod email;
 pub mod env;
 mod github;
+mod imap;
 pub mod inspect;
+pub mod interactivity;
 mod notif;
 mod notifier;
 pub mod polling;
+pub mod replay;
+mod rules;
+mod seen;
+mod sink;
 mod slack;
+mod snooze;
+mod source;
 mod store;
-mod web;
+#[cfg(test)]
+mod testing;
+mod threads;
+pub mod web;
+pub mod webhook;
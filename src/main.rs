@@ -1,24 +1,17 @@
 use anyhow::Result;
-use gharry::{config::Config, env, polling};
-use std::{thread, time::Duration};
+use gharry::{config::Config, dlq, env, interactivity, polling, webhook};
 
 fn main() -> Result<()> {
     let setup = env::setup_exec_env()?;
-    let config = Config::build_default(setup.work_dir)?;
-    'poll_loop: loop {
-        match polling::run(&config) {
-            Ok(()) => return Ok(()),
-            Err(err) => {
-                for cause in err.chain() {
-                    if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
-                        log::info!("some web request failed: {}", err);
-                        log::info!("will retry polling after {} seconds...", 30);
-                        thread::sleep(Duration::from_secs(30));
-                        continue 'poll_loop;
-                    }
-                }
-                return Err(err);
-            }
-        }
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("dlq") {
+        let config = Config::build_default(setup.work_dir)?;
+        return dlq::run(&config, &args[1..]);
     }
+
+    let configs = Config::build_profiles(setup.work_dir)?;
+    interactivity::spawn_servers(&configs)?;
+    let deliveries = webhook::spawn_servers(&configs)?;
+    // Each profile retries polling by itself on failures.
+    polling::run(&configs, &deliveries)
 }
@@ -7,18 +7,25 @@ mod pr_review;
 mod pr_review_comment;
 mod push;
 mod team_review_request;
+#[cfg(test)]
+mod tests;
 mod workflow_cancelled;
 
-use crate::{email::Email, github, slack};
+use crate::{email::Email, github, web::retry};
 use anyhow::Result;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Notification {
+    // A stable identity to avoid delivering the same notification twice, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub detail: NotifDetail,
+    pub reason: Option<github::NotificationReason>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum NotifDetail {
     Unknown {
         sender: String,
@@ -27,6 +34,8 @@ pub enum NotifDetail {
     PrOpened {
         opener: github::User,
         pr: github::IssueInfo,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<github::PrStatus>,
     },
     PrReviewed {
         url: String,
@@ -34,17 +43,24 @@ pub enum NotifDetail {
         state: github::ReviewState,
         commenter: github::User,
         comment: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<github::PrStatus>,
     },
     PrReviewCommented {
         url: String,
         pr: github::IssueInfo,
         commenter: github::User,
         comment: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<github::PrStatus>,
     },
     DirectReviewRequested {
         reviewee: github::User,
         pr: github::IssueInfo,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<github::PrStatus>,
     },
+    // Without a status since it is unsubscribed by default and never shown with one.
     TeamReviewRequested {
         reviewee: github::User,
         pr: github::IssueInfo,
@@ -66,6 +82,8 @@ pub enum NotifDetail {
         diff_url: String,
         committer: github::User,
         commits: Vec<github::CommitInfo>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<github::PrStatus>,
     },
     WorkflowCancelled {
         sender_name: String,
@@ -73,6 +91,219 @@ pub enum NotifDetail {
         workflow_name: String,
         result_url: String,
     },
+    // An issue is updated but we do not know how, since the GitHub Notifications API
+    // does not tell the details.
+    ThreadUpdated {
+        url: String,
+        issue: github::IssueInfo,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NotifKind {
+    Unknown,
+    PrOpened,
+    PrReviewed,
+    PrReviewCommented,
+    DirectReviewRequested,
+    TeamReviewRequested,
+    IssueClosed,
+    Commented,
+    Pushed,
+    WorkflowCancelled,
+    ThreadUpdated,
+}
+
+impl NotifDetail {
+    pub fn kind(&self) -> NotifKind {
+        match self {
+            Self::Unknown { .. } => NotifKind::Unknown,
+            Self::PrOpened { .. } => NotifKind::PrOpened,
+            Self::PrReviewed { .. } => NotifKind::PrReviewed,
+            Self::PrReviewCommented { .. } => NotifKind::PrReviewCommented,
+            Self::DirectReviewRequested { .. } => NotifKind::DirectReviewRequested,
+            Self::TeamReviewRequested { .. } => NotifKind::TeamReviewRequested,
+            Self::IssueClosed { .. } => NotifKind::IssueClosed,
+            Self::Commented { .. } => NotifKind::Commented,
+            Self::Pushed { .. } => NotifKind::Pushed,
+            Self::WorkflowCancelled { .. } => NotifKind::WorkflowCancelled,
+            Self::ThreadUpdated { .. } => NotifKind::ThreadUpdated,
+        }
+    }
+
+    pub fn issue(&self) -> Option<&github::IssueInfo> {
+        match self {
+            Self::PrOpened { pr, .. }
+            | Self::PrReviewed { pr, .. }
+            | Self::PrReviewCommented { pr, .. }
+            | Self::DirectReviewRequested { pr, .. }
+            | Self::TeamReviewRequested { pr, .. }
+            | Self::Pushed { pr, .. } => Some(pr),
+            Self::IssueClosed { issue, .. }
+            | Self::Commented { issue, .. }
+            | Self::ThreadUpdated { issue, .. } => Some(issue),
+            Self::Unknown { .. } | Self::WorkflowCancelled { .. } => None,
+        }
+    }
+
+    // The PR of the notification. Comments and updates are of a PR if their URLs say so.
+    pub fn pr(&self) -> Option<&github::IssueInfo> {
+        match self {
+            Self::PrOpened { pr, .. }
+            | Self::PrReviewed { pr, .. }
+            | Self::PrReviewCommented { pr, .. }
+            | Self::DirectReviewRequested { pr, .. }
+            | Self::TeamReviewRequested { pr, .. }
+            | Self::Pushed { pr, .. } => Some(pr),
+            Self::IssueClosed {
+                issue,
+                is_merge: true,
+                ..
+            } => Some(issue),
+            Self::Commented { url, issue, .. } | Self::ThreadUpdated { url, issue }
+                if url.contains("/pull/") =>
+            {
+                Some(issue)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn repo_fullname(&self) -> Option<String> {
+        match self {
+            Self::WorkflowCancelled { repo_fullname, .. } => Some(repo_fullname.clone()),
+            _ => self.issue().map(|issue| issue.repo.fullname()),
+        }
+    }
+
+    pub fn actor(&self) -> Option<&github::User> {
+        match self {
+            Self::PrOpened { opener: user, .. }
+            | Self::PrReviewed { commenter: user, .. }
+            | Self::PrReviewCommented { commenter: user, .. }
+            | Self::DirectReviewRequested { reviewee: user, .. }
+            | Self::TeamReviewRequested { reviewee: user, .. }
+            | Self::IssueClosed { closer: user, .. }
+            | Self::Commented { commenter: user, .. }
+            | Self::Pushed { committer: user, .. } => Some(user),
+            Self::Unknown { .. } | Self::WorkflowCancelled { .. } | Self::ThreadUpdated { .. } => {
+                None
+            }
+        }
+    }
+
+    pub fn team(&self) -> Option<&str> {
+        match self {
+            Self::TeamReviewRequested { team, .. } => Some(team),
+            _ => None,
+        }
+    }
+
+    // The current status of a PR, if fetched.
+    pub fn pr_status(&self) -> Option<&github::PrStatus> {
+        match self {
+            Self::PrOpened { status, .. }
+            | Self::PrReviewed { status, .. }
+            | Self::PrReviewCommented { status, .. }
+            | Self::DirectReviewRequested { status, .. }
+            | Self::Pushed { status, .. } => status.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn review_state(&self) -> Option<&github::ReviewState> {
+        match self {
+            Self::PrReviewed { state, .. } => Some(state),
+            _ => None,
+        }
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Self::PrReviewed { url, .. }
+            | Self::PrReviewCommented { url, .. }
+            | Self::Commented { url, .. }
+            | Self::ThreadUpdated { url, .. } => Some(url),
+            Self::Pushed { diff_url, .. } => Some(diff_url),
+            Self::WorkflowCancelled { result_url, .. } => Some(result_url),
+            _ => None,
+        }
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        match self {
+            Self::PrReviewed { comment, .. }
+            | Self::PrReviewCommented { comment, .. }
+            | Self::Commented { comment, .. } => Some(comment),
+            _ => None,
+        }
+    }
+}
+
+/// An email looks like a known kind of notification but its content is not as expected.
+/// The email is notified as a plain one in this case, unless even its subject is broken.
+/// The emails which cannot be parsed at all are put into the dead letters.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidNumber { name: &'static str, value: String },
+    CommitCountMismatch { expected: usize, actual: usize },
+    Panicked { message: String },
+    NoReviewHead,
+    NoReviewFooter,
+    FooterBeforeHead,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidNumber { name, value } => write!(f, "invalid {}: {}", name, value),
+            Self::CommitCountMismatch { expected, actual } => {
+                write!(f, "commit count mismatch: {} != {}", actual, expected)
+            }
+            Self::Panicked { message } => write!(f, "parser panicked: {}", message),
+            Self::NoReviewHead => write!(f, "no head comment of review in email"),
+            Self::NoReviewFooter => write!(f, "no footer of review in email"),
+            Self::FooterBeforeHead => write!(f, "footer before head comment of review in email"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn parse_number<T: FromStr>(name: &'static str, value: &str) -> Result<T, ParseError> {
+    value.parse().map_err(|_| ParseError::InvalidNumber {
+        name,
+        value: value.to_string(),
+    })
+}
+
+// Get the current status of a PR to show with its notification. The status is optional,
+// so only the errors worth retrying are returned.
+fn fetch_pr_status(cx: &BuildContext, pr: &github::IssueInfo) -> Result<Option<github::PrStatus>> {
+    let params = github::GetPrStatusParams {
+        repo: &pr.repo,
+        number: pr.number,
+    };
+    match cx.github.get_pr_status(&params) {
+        Ok(status) => Ok(status),
+        Err(err) if retry::is_retryable(&err) => Err(err),
+        Err(err) => {
+            log::warn!(
+                "failed to fetch status of {}#{}: {:#}",
+                pr.repo.fullname(),
+                pr.number,
+                err
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn pr_status_lookup(pr: &github::IssueInfo) -> github::Lookup {
+    github::Lookup::PrStatus {
+        repo: pr.repo.clone(),
+        number: pr.number,
+    }
 }
 
 #[derive(Debug)]
@@ -80,25 +311,48 @@ pub struct BuildContext<'a> {
     pub github: &'a github::Client,
 }
 
-pub fn build_notifications(
-    cx: BuildContext,
-    messages: Vec<slack::Message>,
-) -> Result<Vec<Notification>> {
-    let notifs = messages
+/// An email read as a GitHub notification, whose details are not looked up yet.
+/// Reading emails first lets their lookups be prefetched at once.
+#[derive(Debug)]
+pub struct Draft {
+    pub email: Email,
+    enotif: github::EmailNotif,
+}
+
+impl Draft {
+    pub fn read(cx: &BuildContext, email: Email) -> Result<Self> {
+        let enotif = github::build_notif_from_email(&email, &cx.github.web_bases())?;
+        Ok(Draft { email, enotif })
+    }
+
+    pub fn build(self, cx: &BuildContext) -> Result<Notification> {
+        Parser::parse(cx, self.email, self.enotif)
+    }
+}
+
+pub fn build_notifications(cx: BuildContext, emails: Vec<Email>) -> Result<Vec<Notification>> {
+    let drafts = emails
+        .into_iter()
+        .map(|email| Draft::read(&cx, email))
+        .collect::<Result<Vec<_>>>()?;
+    prefetch(&cx, &drafts);
+    drafts.into_iter().map(|draft| draft.build(&cx)).collect()
+}
+
+// Fetch what the parsers need for the emails at once before parsing them one by one.
+// A failure is not fatal since the parsers can still fetch them by themselves.
+pub fn prefetch<'a>(cx: &BuildContext, drafts: impl IntoIterator<Item = &'a Draft>) {
+    let lookups = drafts
         .into_iter()
-        .filter_map(slack::extract_email_from_message)
-        .try_fold(Vec::new(), |mut notifs, email| {
-            github::build_notif_from_email(&email)
-                .and_then(|enotif| {
-                    let n = Parser::parse(&cx, email, enotif)?;
-                    Ok(Notification { detail: n.detail })
-                })
-                .map(|notif| {
-                    notifs.push(notif);
-                    notifs
-                })
-        })?;
-    Ok(notifs)
+        .flat_map(|draft| PARSERS.iter().flat_map(move |p| p.needs(&draft.enotif)))
+        .collect::<Vec<_>>();
+    if let Err(err) = cx.github.prefetch(&lookups) {
+        log::warn!("failed to prefetch, fall back to REST API: {:#}", err);
+    }
+}
+
+pub fn build_notification(cx: &BuildContext, email: Email) -> Result<Notification> {
+    Draft::read(cx, email)?.build(cx)
 }
 
 const PARSERS: [Parser; 9] = [
@@ -129,8 +383,22 @@ enum Parser {
 impl Parser {
     fn parse(cx: &BuildContext, email: Email, enotif: github::EmailNotif) -> Result<Notification> {
         for p in PARSERS {
-            if let Some(notif) = p.try_parse(cx, &email, &enotif)? {
-                return Ok(notif);
+            match p.try_parse(cx, &email, &enotif) {
+                Ok(Some(notif)) => return Ok(notif),
+                Ok(None) => {}
+                // Other errors such as API failures are returned as is so that they can be retried.
+                Err(err) => match err.downcast_ref::<ParseError>() {
+                    Some(parse_err) => {
+                        log::warn!(
+                            "{:?} parser failed on {:?}, fall back to plain: {}",
+                            p,
+                            email.subject,
+                            parse_err
+                        );
+                        break;
+                    }
+                    None => return Err(err),
+                },
             }
         }
         Ok(plain::parse(email, enotif))
@@ -154,4 +422,19 @@ impl Parser {
             Self::WorkflowCancelled => workflow_cancelled::try_parse(email, enotif),
         }
     }
+
+    // What the parser would fetch from GitHub if the email is of its kind.
+    fn needs(&self, enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+        match *self {
+            Self::PrOpen => pr_open::needs(enotif),
+            Self::PrReview => pr_review::needs(enotif),
+            Self::PrReviewComment => pr_review_comment::needs(enotif),
+            Self::DirectReviewRequest => direct_review_request::needs(enotif),
+            Self::TeamReviewRequest => team_review_request::needs(enotif),
+            Self::IssueComment => issue_comment::needs(enotif),
+            Self::Push => push::needs(enotif),
+            // Issue events are not looked up since their GraphQL counterparts lack the REST IDs.
+            Self::IssueClosed | Self::WorkflowCancelled => Vec::new(),
+        }
+    }
 }
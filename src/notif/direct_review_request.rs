@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,13 +7,10 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(_)) => {
-            let re = Regex::new(r"^@(?P<user>[^\s]+) requested your review on:")?;
-            let reviewee = match re.captures(&enotif.lines[0]) {
+            let reviewee = match find_reviewee(&enotif.lines[0])? {
                 None => return Ok(None),
-                Some(caps) => {
-                    let params = github::GetUserParams {
-                        name: &caps["user"],
-                    };
+                Some(login) => {
+                    let params = github::GetUserParams { name: &login };
                     match cx.github.get_user(&params)? {
                         None => return Ok(None),
                         Some(reviewee) => reviewee,
@@ -22,12 +18,33 @@ pub(super) fn try_parse(
                 }
             };
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::DirectReviewRequested {
                     reviewee,
                     pr: issue.clone(),
+                    status: notif::fetch_pr_status(cx, issue)?,
                 },
             }))
         }
         _ => Ok(None),
     }
 }
+
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(_)) => match find_reviewee(&enotif.lines[0]) {
+            Ok(Some(login)) => vec![
+                github::Lookup::User { login },
+                notif::pr_status_lookup(issue),
+            ],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_reviewee(line: &str) -> Result<Option<String>> {
+    let re = regex!(r"^@(?P<user>[^\s]+) requested your review on:");
+    Ok(re.captures(line).map(|caps| caps["user"].to_string()))
+}
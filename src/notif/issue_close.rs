@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -12,10 +11,10 @@ pub(super) fn try_parse(
                 return Ok(None);
             }
 
-            let re = Regex::new(r"#event-(?P<id>\d+)$")?;
-            let event_id = match re.captures(&url) {
+            let re = regex!(r"#event-(?P<id>\d+)$");
+            let event_id = match re.captures(url) {
                 None => return Ok(None),
-                Some(caps) => caps["id"].parse().unwrap(),
+                Some(caps) => notif::parse_number("event ID", &caps["id"])?,
             };
 
             let params = github::GetIssueEventParams {
@@ -28,6 +27,8 @@ pub(super) fn try_parse(
             };
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::IssueClosed {
                     closer: event.actor,
                     issue: issue.clone(),
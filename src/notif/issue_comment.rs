@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,10 +7,9 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(url)) => {
-            let re = Regex::new(r"#issuecomment-(?P<id>\d+)$")?;
-            let comment_id = match re.captures(&url) {
+            let comment_id = match find_comment_id(url)? {
                 None => return Ok(None),
-                Some(caps) => caps["id"].parse().unwrap(),
+                Some(id) => id,
             };
 
             let params = github::GetIssueCommentParams {
@@ -24,6 +22,8 @@ pub(super) fn try_parse(
             };
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::Commented {
                     url: url.clone(),
                     commenter: comment.user,
@@ -35,3 +35,25 @@ pub(super) fn try_parse(
         _ => Ok(None),
     }
 }
+
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(url)) => match find_comment_id(url) {
+            Ok(Some(comment_id)) => vec![github::Lookup::IssueComment {
+                repo: issue.repo.clone(),
+                number: issue.number,
+                comment_id,
+            }],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_comment_id(url: &str) -> Result<Option<usize>> {
+    let re = regex!(r"#issuecomment-(?P<id>\d+)$");
+    match re.captures(url) {
+        None => Ok(None),
+        Some(caps) => Ok(Some(notif::parse_number("comment ID", &caps["id"])?)),
+    }
+}
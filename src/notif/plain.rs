@@ -2,6 +2,8 @@ use crate::{email::Email, github, notif};
 
 pub(super) fn parse(email: Email, enotif: github::EmailNotif) -> notif::Notification {
     notif::Notification {
+        id: enotif.id,
+        reason: enotif.reason,
         detail: notif::NotifDetail::Unknown {
             sender: email.sender_name,
             body: enotif.lines,
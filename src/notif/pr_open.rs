@@ -21,9 +21,12 @@ pub(super) fn try_parse(
             };
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::PrOpened {
                     opener: pr.user,
                     pr: issue.clone(),
+                    status: notif::fetch_pr_status(cx, issue)?,
                 },
             }))
         }
@@ -31,6 +34,19 @@ pub(super) fn try_parse(
     }
 }
 
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(_)) if is_pr_open_notif(enotif) => vec![
+            github::Lookup::Issue {
+                repo: issue.repo.clone(),
+                number: issue.number,
+            },
+            notif::pr_status_lookup(issue),
+        ],
+        _ => Vec::new(),
+    }
+}
+
 fn is_pr_open_notif(enotif: &github::EmailNotif) -> bool {
     let t = "You can view, comment on, or merge this pull request online at:";
     enotif.lines.iter().any(|l| l.starts_with(t))
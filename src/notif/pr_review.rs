@@ -1,6 +1,5 @@
 use crate::{github, notif};
-use anyhow::{anyhow, Result};
-use regex::Regex;
+use anyhow::Result;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,10 +7,9 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(url)) => {
-            let re = Regex::new(r"#pullrequestreview-(?P<id>\d+)$")?;
-            let review_id = match re.captures(&url) {
+            let review_id = match find_review_id(url)? {
                 None => return Ok(None),
-                Some(caps) => caps["id"].parse().unwrap(),
+                Some(id) => id,
             };
 
             let params = github::GetPrReviewParams {
@@ -35,12 +33,15 @@ pub(super) fn try_parse(
             };
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::PrReviewed {
                     url: url.clone(),
                     pr: issue.clone(),
                     state: review.state,
                     commenter: review.user,
                     comment,
+                    status: notif::fetch_pr_status(cx, issue)?,
                 },
             }))
         }
@@ -48,7 +49,35 @@ pub(super) fn try_parse(
     }
 }
 
-fn extract_whole_comments(lines: &[String], review: &github::Review) -> Result<String> {
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(url)) => match find_review_id(url) {
+            Ok(Some(review_id)) => vec![
+                github::Lookup::PrReview {
+                    repo: issue.repo.clone(),
+                    pr_number: issue.number,
+                    review_id,
+                },
+                notif::pr_status_lookup(issue),
+            ],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_review_id(url: &str) -> Result<Option<usize>> {
+    let re = regex!(r"#pullrequestreview-(?P<id>\d+)$");
+    match re.captures(url) {
+        None => Ok(None),
+        Some(caps) => Ok(Some(notif::parse_number("review ID", &caps["id"])?)),
+    }
+}
+
+fn extract_whole_comments(
+    lines: &[String],
+    review: &github::Review,
+) -> Result<String, notif::ParseError> {
     // [Expected text is like following:]
     //
     // @foo commented on this pull request.
@@ -69,22 +98,25 @@ fn extract_whole_comments(lines: &[String], review: &github::Review) -> Result<S
     // Detect the first and last line index of comment part.
     let head_cmt = review_head_comment(review);
     let head_idx = match lines.iter().position(|l| l == &head_cmt) {
-        None => return Err(anyhow!("no head comment in email: {}", lines.join("\n"))),
+        None => return Err(notif::ParseError::NoReviewHead),
         Some(idx) => idx,
     };
     let foot_idx = match lines.iter().rposition(|l| l == "-- ") {
-        None => return Err(anyhow!("no footer comment in email: {}", lines.join("\n"))),
+        None => return Err(notif::ParseError::NoReviewFooter),
         Some(idx) => idx,
     };
 
+    if foot_idx <= head_idx {
+        return Err(notif::ParseError::FooterBeforeHead);
+    }
+
     // Remove the first and last lines if they are empty.
     let mut comments = &lines[(head_idx + 1)..foot_idx];
-    if comments[0].trim() == "" {
+    if comments.first().is_some_and(|l| l.trim().is_empty()) {
         comments = &comments[1..];
     }
-    let last_idx = comments.len() - 1;
-    if comments[last_idx].trim() == "" {
-        comments = &comments[..last_idx];
+    if comments.last().is_some_and(|l| l.trim().is_empty()) {
+        comments = &comments[..comments.len() - 1];
     }
 
     Ok(comments.join("\n"))
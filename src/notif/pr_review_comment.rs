@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,10 +7,9 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(url)) => {
-            let re = Regex::new(r"#discussion_r(?P<id>\d+)$")?;
-            let review_comment_id = match re.captures(&url) {
+            let review_comment_id = match find_review_comment_id(url)? {
                 None => return Ok(None),
-                Some(caps) => caps["id"].parse().unwrap(),
+                Some(id) => id,
             };
 
             let params = github::GetPrReviewCommentParams {
@@ -24,14 +22,42 @@ pub(super) fn try_parse(
             };
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::PrReviewCommented {
                     url: url.clone(),
                     pr: issue.clone(),
                     commenter: comment.user,
                     comment: comment.body,
+                    status: notif::fetch_pr_status(cx, issue)?,
                 },
             }))
         }
         _ => Ok(None),
     }
 }
+
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(url)) => match find_review_comment_id(url) {
+            Ok(Some(review_comment_id)) => vec![
+                github::Lookup::PrReviewComment {
+                    repo: issue.repo.clone(),
+                    pr_number: issue.number,
+                    review_comment_id,
+                },
+                notif::pr_status_lookup(issue),
+            ],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_review_comment_id(url: &str) -> Result<Option<usize>> {
+    let re = regex!(r"#discussion_r(?P<id>\d+)$");
+    match re.captures(url) {
+        None => Ok(None),
+        Some(caps) => Ok(Some(notif::parse_number("review comment ID", &caps["id"])?)),
+    }
+}
@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,32 +7,36 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(url)) => {
-            let re = Regex::new(r"^@(?P<user>[^\s]+) pushed (?P<count>\d+) commits?\.")?;
-            let (committer, commit_cnt) = match re.captures(&enotif.lines[0]) {
+            let (committer, commit_cnt) = match find_committer_and_count(&enotif.lines[0])? {
                 None => return Ok(None),
-                Some(caps) => {
-                    let params = github::GetUserParams {
-                        name: &caps["user"],
-                    };
+                Some((login, count)) => {
+                    let params = github::GetUserParams { name: &login };
                     let user = match cx.github.get_user(&params)? {
                         None => return Ok(None),
                         Some(user) => user,
                     };
-                    let commit_cnt = caps["count"].parse::<usize>().unwrap();
+                    let commit_cnt = notif::parse_number("commit count", &count)?;
                     (user, commit_cnt)
                 }
             };
             let commits = extract_commit_info(&enotif.lines)?;
             if commits.len() != commit_cnt {
-                panic!("commit count mismatch: {} != {}", commits.len(), commit_cnt);
+                return Err(notif::ParseError::CommitCountMismatch {
+                    expected: commit_cnt,
+                    actual: commits.len(),
+                }
+                .into());
             }
 
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::Pushed {
                     pr: issue.clone(),
                     diff_url: url.clone(),
                     committer,
                     commits,
+                    status: notif::fetch_pr_status(cx, issue)?,
                 },
             }))
         }
@@ -41,8 +44,28 @@ pub(super) fn try_parse(
     }
 }
 
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(issue), Some(_)) => match find_committer_and_count(&enotif.lines[0]) {
+            Ok(Some((login, _))) => vec![
+                github::Lookup::User { login },
+                notif::pr_status_lookup(issue),
+            ],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_committer_and_count(line: &str) -> Result<Option<(String, String)>> {
+    let re = regex!(r"^@(?P<user>[^\s]+) pushed (?P<count>\d+) commits?\.");
+    Ok(re
+        .captures(line)
+        .map(|caps| (caps["user"].to_string(), caps["count"].to_string())))
+}
+
 fn extract_commit_info(lines: &[String]) -> Result<Vec<github::CommitInfo>> {
-    let hash_re = Regex::new(r"^[a-z0-9]{40}")?;
+    let hash_re = regex!(r"^[a-z0-9]{40}");
     let commits = lines
         .iter()
         .filter_map(|line| match line.split_once(' ') {
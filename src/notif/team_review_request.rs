@@ -1,6 +1,5 @@
 use crate::{github, notif};
 use anyhow::Result;
-use regex::Regex;
 
 pub(super) fn try_parse(
     cx: &notif::BuildContext,
@@ -8,21 +7,20 @@ pub(super) fn try_parse(
 ) -> Result<Option<notif::Notification>> {
     match (&enotif.detected_issue, &enotif.github_url) {
         (Some(issue), Some(_)) => {
-            let re = Regex::new(r"^@(?P<user>[^\s]+) requested review from @(?P<team>[^\s]+) on:")?;
-            let (reviewee, team) = match re.captures(&enotif.lines[0]) {
+            let (reviewee, team) = match find_reviewee_and_team(&enotif.lines[0])? {
                 None => return Ok(None),
-                Some(caps) => {
-                    let params = github::GetUserParams {
-                        name: &caps["user"],
-                    };
+                Some((login, team)) => {
+                    let params = github::GetUserParams { name: &login };
                     let reviewee = match cx.github.get_user(&params)? {
                         None => return Ok(None),
                         Some(reviewee) => reviewee,
                     };
-                    (reviewee, caps["team"].to_string())
+                    (reviewee, team)
                 }
             };
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::TeamReviewRequested {
                     reviewee,
                     pr: issue.clone(),
@@ -33,3 +31,20 @@ pub(super) fn try_parse(
         _ => Ok(None),
     }
 }
+
+pub(super) fn needs(enotif: &github::EmailNotif) -> Vec<github::Lookup> {
+    match (&enotif.detected_issue, &enotif.github_url) {
+        (Some(_), Some(_)) => match find_reviewee_and_team(&enotif.lines[0]) {
+            Ok(Some((login, _))) => vec![github::Lookup::User { login }],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn find_reviewee_and_team(line: &str) -> Result<Option<(String, String)>> {
+    let re = regex!(r"^@(?P<user>[^\s]+) requested review from @(?P<team>[^\s]+) on:");
+    Ok(re
+        .captures(line)
+        .map(|caps| (caps["user"].to_string(), caps["team"].to_string())))
+}
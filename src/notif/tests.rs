@@ -0,0 +1,194 @@
+//! Tests of the notification parsers using the email fixtures in `tests/fixtures/emails`.
+//! Each `<name>.eml` is parsed with the fake GitHub API which serves `tests/fixtures/github`,
+//! and the result is compared with the golden file `<name>.json`.
+//! Run tests with `UPDATE_GOLDEN=1` to regenerate the golden files.
+
+use crate::{
+    email, notif,
+    testing::{fake_github, fixtures_dir},
+};
+use std::fs;
+
+fn load_email(name: &str) -> email::Email {
+    let raw = fs::read(fixtures_dir(format!("emails/{}.eml", name))).unwrap();
+    email::parse_message(&raw).unwrap()
+}
+
+fn build(email: email::Email) -> notif::Notification {
+    let fake = fake_github();
+    let github = fake.client("https://github.com");
+    let cx = notif::BuildContext { github: &github };
+    notif::build_notification(&cx, email).unwrap()
+}
+
+fn assert_golden(name: &str) {
+    assert_golden_with_web_base(name, "https://github.com");
+}
+
+fn assert_golden_with_web_base(name: &str, web_base: &str) {
+    let fake = fake_github();
+    let github = fake.client(web_base);
+
+    let emails_dir = fixtures_dir("emails");
+    let raw = fs::read(emails_dir.join(format!("{}.eml", name))).unwrap();
+    let email = email::parse_message(&raw).unwrap();
+
+    let cx = notif::BuildContext { github: &github };
+    let notifs = notif::build_notifications(cx, vec![email]).unwrap();
+    let actual = serde_json::to_string_pretty(&notifs).unwrap() + "\n";
+
+    let golden_path = emails_dir.join(format!("{}.json", name));
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        fs::write(&golden_path, &actual).unwrap();
+    }
+    let expected = fs::read_to_string(&golden_path).unwrap_or_default();
+    assert_eq!(
+        actual,
+        expected,
+        "golden mismatch: {}",
+        golden_path.display()
+    );
+}
+
+#[test]
+fn pr_open() {
+    assert_golden("pr_open");
+}
+
+#[test]
+fn pr_review_approved() {
+    assert_golden("pr_review_approved");
+}
+
+#[test]
+fn pr_review_commented() {
+    assert_golden("pr_review_commented");
+}
+
+#[test]
+fn pr_review_comment() {
+    assert_golden("pr_review_comment");
+}
+
+#[test]
+fn direct_review_request() {
+    assert_golden("direct_review_request");
+}
+
+#[test]
+fn team_review_request() {
+    assert_golden("team_review_request");
+}
+
+#[test]
+fn issue_closed() {
+    assert_golden("issue_closed");
+}
+
+#[test]
+fn pr_merged() {
+    assert_golden("pr_merged");
+}
+
+#[test]
+fn issue_comment() {
+    assert_golden("issue_comment");
+}
+
+#[test]
+fn push() {
+    assert_golden("push");
+}
+
+#[test]
+fn workflow_cancelled() {
+    assert_golden("workflow_cancelled");
+}
+
+#[test]
+fn unknown() {
+    assert_golden("unknown");
+}
+
+#[test]
+fn enterprise_server_issue_comment() {
+    assert_golden_with_web_base("ghes_issue_comment", "https://ghe.example.com");
+}
+
+#[test]
+fn commit_count_mismatch_falls_back_to_plain() {
+    let mut email = load_email("push");
+    email.text_body = email
+        .text_body
+        .replace("pushed 2 commits", "pushed 3 commits");
+    let notif = build(email);
+    assert_eq!(notif.detail.kind(), notif::NotifKind::Unknown);
+}
+
+#[test]
+fn too_large_comment_id_falls_back_to_plain() {
+    let mut email = load_email("issue_comment");
+    email.text_body = email
+        .text_body
+        .replace("#issuecomment-", "#issuecomment-99999999999999999999");
+    let notif = build(email);
+    assert_eq!(notif.detail.kind(), notif::NotifKind::Unknown);
+}
+
+#[test]
+fn review_without_footer_uses_review_body() {
+    let mut email = load_email("pr_review_commented");
+    email.text_body = email.text_body.replace("\n-- ", "\n");
+    let notif = build(email);
+    assert_eq!(
+        notif.detail.comment(),
+        Some("I left one comment, please take a look.")
+    );
+}
+
+#[test]
+fn short_email_is_plain() {
+    let email = email::Email {
+        id: None,
+        subject: String::from("[acme/widgets] Short (#43)"),
+        sender_name: String::from("alice"),
+        text_body: String::from("Hi"),
+    };
+    let notif = build(email);
+    match notif.detail {
+        notif::NotifDetail::Unknown { body, .. } => assert_eq!(body, ["Hi"]),
+        detail => panic!("unexpected detail: {:?}", detail),
+    }
+}
+
+#[test]
+fn too_large_issue_number_is_parse_error() {
+    let email = email::Email {
+        id: None,
+        subject: String::from("[acme/widgets] Huge (#99999999999999999999)"),
+        sender_name: String::from("alice"),
+        text_body: String::from("Hi"),
+    };
+    let fake = fake_github();
+    let github = fake.client("https://github.com");
+    let cx = notif::BuildContext { github: &github };
+    let err = notif::build_notification(&cx, email).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<notif::ParseError>(),
+        Some(&notif::ParseError::InvalidNumber {
+            name: "issue number",
+            value: String::from("99999999999999999999"),
+        })
+    );
+}
+
+#[test]
+fn parse_error_messages() {
+    let err = notif::parse_number::<usize>("comment ID", "x").unwrap_err();
+    assert_eq!(err.to_string(), "invalid comment ID: x");
+    let err = notif::ParseError::CommitCountMismatch {
+        expected: 3,
+        actual: 2,
+    };
+    assert_eq!(err.to_string(), "commit count mismatch: 2 != 3");
+}
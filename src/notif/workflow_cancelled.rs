@@ -13,20 +13,21 @@ pub(super) fn try_parse(
     let mut workflow_name: Option<String> = None;
     let mut result_url: Option<String> = None;
     for l in &enotif.lines {
-        match l.splitn(2, ":").collect::<Vec<&str>>()[..] {
-            [name, value] => match name {
+        if let Some((name, value)) = l.split_once(':') {
+            match name {
                 "Repository" => repo_fullname = Some(value.trim().to_string()),
                 "Workflow" => workflow_name = Some(value.trim().to_string()),
                 "View results" => result_url = Some(value.trim().to_string()),
                 _ => {}
-            },
-            _ => {}
-        };
+            }
+        }
     }
 
     match (repo_fullname, workflow_name, result_url) {
         (Some(repo_fullname), Some(workflow_name), Some(result_url)) => {
             Ok(Some(notif::Notification {
+                id: enotif.id.clone(),
+                reason: enotif.reason,
                 detail: notif::NotifDetail::WorkflowCancelled {
                     sender_name: email.sender_name.clone(),
                     repo_fullname,
@@ -1,7 +1,13 @@
+mod blocks;
+
 use crate::{
-    github,
+    config::{Config, MessageFormat},
+    digest, github,
     notif::{NotifDetail, Notification},
+    rules::{Decision, Delivery},
     slack,
+    store::Store,
+    threads::ThreadState,
 };
 use anyhow::Result;
 
@@ -15,44 +21,96 @@ struct NotifMessage {
 const DEFAULT_USER_NAME: &str = "Gharry";
 const DEFAULT_ICON_EMOJI: &str = ":carousel_horse:";
 
-pub fn notify_by_slack(slack: &slack::Client, channel: &str, notif: Notification) -> Result<()> {
+pub fn notify_by_slack(
+    slack: &slack::Client,
+    config: &Config,
+    decision: &Decision,
+    notif: &Notification,
+    threads: Option<&mut Store<ThreadState>>,
+) -> Result<()> {
     log::debug!("notifying {:?}", notif);
 
-    let mention = if should_alert(&notif.detail) {
-        "\n<!here>"
-    } else {
-        ""
+    // A channel specified by a rule takes precedence over the per-repository routes.
+    let repo_fullname = notif.detail.repo_fullname();
+    let channel = match &decision.channel {
+        Some(channel) => channel.as_str(),
+        None => config.slack.dest_channel_for(repo_fullname.as_deref()),
     };
-    match generate_message(notif) {
+
+    // Notify the one we failed to unsubscribe just like an ordinary notification.
+    let alert = matches!(decision.delivery, Delivery::Alert | Delivery::Unsubscribe);
+    let mention = if alert { "\n<!here>" } else { "" };
+    match generate_message(notif, &config.github.endpoints.web_base) {
         None => {
             log::info!("Skip sending notification");
         }
         Some(msg) => {
             log::info!("Sending notification...");
-            let text = format!("{}{}", msg.text, mention);
-            slack.chat_post_message(&slack::ChatMessage {
+            // Show why the user receives it, as GitHub does in the email footer.
+            let reason = match notif.reason {
+                Some(reason) => format!("\n_because {}_", reason.description()),
+                None => String::new(),
+            };
+            let text = format!("{}{}{}", msg.text, reason, mention);
+            let blocks = match config.slack.message_format {
+                MessageFormat::Text => None,
+                MessageFormat::Blocks => {
+                    let web_base = &config.github.endpoints.web_base;
+                    let interactive = config.interactivity.is_some();
+                    blocks::render(notif, web_base, alert, interactive)
+                }
+            };
+
+            // Reply to the thread of the issue if we have posted about it before.
+            let issue = notif.detail.issue();
+            let thread_ts = match (&threads, issue) {
+                (Some(threads), Some(issue)) => {
+                    threads.state.find(channel, issue).map(String::from)
+                }
+                _ => None,
+            };
+            let res = slack.chat_post_message(&slack::ChatMessage {
                 channel,
                 text: &text,
+                blocks: blocks.as_ref(),
                 username: Some(msg.user_name.as_deref().unwrap_or(DEFAULT_USER_NAME)),
                 icon_url: msg.icon_url.as_deref(),
                 icon_emoji: icon_emoji(&msg.icon_url),
                 unfurl_links: false,
                 unfurl_media: false,
+                thread_ts: thread_ts.as_deref(),
+                reply_broadcast: thread_ts.is_some() && alert && config.slack.reply_broadcast,
             })?;
+
+            if let (Some(threads), Some(issue), None) = (threads, issue, thread_ts) {
+                threads.update_state_with(|state| state.insert(channel, issue, res.ts))?;
+            }
         }
     }
 
     Ok(())
 }
 
-fn should_alert(detail: &NotifDetail) -> bool {
-    !matches!(
-        detail,
-        NotifDetail::Pushed { .. }
-            | NotifDetail::PrOpened { .. }
-            | NotifDetail::IssueClosed { .. }
-            | NotifDetail::WorkflowCancelled { .. },
-    )
+// Split a digest into messages which Slack accepts.
+pub fn digest_messages(config: &Config, notifs: &[&Notification]) -> Vec<String> {
+    generate_digest(notifs, &config.github.endpoints.web_base)
+}
+
+pub fn notify_digest_by_slack(slack: &slack::Client, channel: &str, text: &str) -> Result<()> {
+    log::info!("Sending digest message to {}...", channel);
+    slack.chat_post_message(&slack::ChatMessage {
+        channel,
+        text,
+        blocks: None,
+        username: Some(DEFAULT_USER_NAME),
+        icon_url: None,
+        icon_emoji: Some(DEFAULT_ICON_EMOJI),
+        unfurl_links: false,
+        unfurl_media: false,
+        thread_ts: None,
+        reply_broadcast: false,
+    })?;
+    Ok(())
 }
 
 fn icon_emoji(icon_url: &Option<String>) -> Option<&str> {
@@ -62,21 +120,21 @@ fn icon_emoji(icon_url: &Option<String>) -> Option<&str> {
     }
 }
 
-fn generate_message(notif: Notification) -> Option<NotifMessage> {
-    match notif.detail {
+fn generate_message(notif: &Notification, web_base: &str) -> Option<NotifMessage> {
+    match &notif.detail {
         NotifDetail::Unknown { sender, body } => Some(NotifMessage {
             text: body.join("\n"),
-            user_name: Some(sender),
+            user_name: Some(sender.clone()),
             icon_url: None,
         }),
 
-        NotifDetail::PrOpened { opener, pr } => {
+        NotifDetail::PrOpened { opener, pr, status } => {
             let login = format!("@{}", opener.login);
-            let pr_sbj = issue_subject(&pr, None);
+            let pr_sbj = issue_subject(web_base, pr, None);
             Some(NotifMessage {
-                text: format!("{} opened {}", login, pr_sbj),
+                text: format!("{} opened {}{}", login, pr_sbj, status_line(status)),
                 user_name: Some(login),
-                icon_url: Some(opener.avatar_url),
+                icon_url: Some(opener.avatar_url.clone()),
             })
         }
 
@@ -86,15 +144,23 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
             state,
             commenter,
             comment,
+            status,
         } => {
             let login = format!("@{}", commenter.login);
-            let pr_sbj = issue_subject(&pr, Some(&url));
-            let state_icon = review_state_emoji(&state);
-            let text = format!("{} {} {}\n{}", login, state_icon, pr_sbj, comment);
+            let pr_sbj = issue_subject(web_base, pr, Some(url));
+            let state_icon = review_state_emoji(state);
+            let text = format!(
+                "{} {} {}{}\n{}",
+                login,
+                state_icon,
+                pr_sbj,
+                status_line(status),
+                comment
+            );
             Some(NotifMessage {
                 text,
                 user_name: Some(login),
-                icon_url: Some(commenter.avatar_url),
+                icon_url: Some(commenter.avatar_url.clone()),
             })
         }
 
@@ -103,23 +169,39 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
             pr,
             commenter,
             comment,
+            status,
         } => {
             let login = format!("@{}", commenter.login);
-            let pr_sbj = issue_subject(&pr, Some(&url));
+            let pr_sbj = issue_subject(web_base, pr, Some(url));
             Some(NotifMessage {
-                text: format!("{} 💬  {}\n{}", login, pr_sbj, comment),
+                text: format!(
+                    "{} 💬  {}{}\n{}",
+                    login,
+                    pr_sbj,
+                    status_line(status),
+                    comment
+                ),
                 user_name: Some(login),
-                icon_url: Some(commenter.avatar_url),
+                icon_url: Some(commenter.avatar_url.clone()),
             })
         }
 
-        NotifDetail::DirectReviewRequested { reviewee, pr } => {
+        NotifDetail::DirectReviewRequested {
+            reviewee,
+            pr,
+            status,
+        } => {
             let login = format!("@{}", reviewee.login);
-            let pr_sbj = issue_subject(&pr, None);
+            let pr_sbj = issue_subject(web_base, pr, None);
             Some(NotifMessage {
-                text: format!("{} requested your review 🔔 on {}", login, pr_sbj),
+                text: format!(
+                    "{} requested your review 🔔 on {}{}",
+                    login,
+                    pr_sbj,
+                    status_line(status)
+                ),
                 user_name: Some(login),
-                icon_url: Some(reviewee.avatar_url),
+                icon_url: Some(reviewee.avatar_url.clone()),
             })
         }
 
@@ -131,12 +213,12 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
             is_merge,
         } => {
             let login = format!("@{}", closer.login);
-            let issue_sbj = issue_subject(&issue, None);
-            let action = if is_merge { "merged" } else { "closed" };
+            let issue_sbj = issue_subject(web_base, issue, None);
+            let action = if *is_merge { "merged" } else { "closed" };
             Some(NotifMessage {
                 text: format!("{} {} {}", login, action, issue_sbj),
                 user_name: Some(login),
-                icon_url: Some(closer.avatar_url),
+                icon_url: Some(closer.avatar_url.clone()),
             })
         }
 
@@ -147,11 +229,11 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
             comment,
         } => {
             let login = format!("@{}", commenter.login);
-            let issue_sbj = issue_subject(&issue, Some(&url));
+            let issue_sbj = issue_subject(web_base, issue, Some(url));
             Some(NotifMessage {
                 text: format!("{} 💬  {}\n{}", login, issue_sbj, comment),
                 user_name: Some(login),
-                icon_url: Some(commenter.avatar_url),
+                icon_url: Some(commenter.avatar_url.clone()),
             })
         }
 
@@ -160,6 +242,7 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
             diff_url,
             committer,
             commits,
+            status,
         } => {
             let login = format!("@{}", committer.login);
             let commits_summary = format!(
@@ -167,15 +250,20 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
                 commits.len(),
                 if commits.len() == 1 { "" } else { "s" }
             );
-            let joined_msg = join_commit_messages(&commits, 10);
-            let pr_sbj = issue_subject(&pr, None);
+            let joined_msg = join_commit_messages(commits, 10);
+            let pr_sbj = issue_subject(web_base, pr, None);
             Some(NotifMessage {
                 text: format!(
-                    "{} pushed <{}|{}> to {}\n{}",
-                    login, diff_url, commits_summary, pr_sbj, joined_msg
+                    "{} pushed <{}|{}> to {}{}\n{}",
+                    login,
+                    diff_url,
+                    commits_summary,
+                    pr_sbj,
+                    status_line(status),
+                    joined_msg
                 ),
                 user_name: Some(login),
-                icon_url: Some(committer.avatar_url),
+                icon_url: Some(committer.avatar_url.clone()),
             })
         }
 
@@ -189,16 +277,160 @@ fn generate_message(notif: Notification) -> Option<NotifMessage> {
                 "[{}] workflow cancelled: {}\n{}",
                 repo_fullname, workflow_name, result_url,
             ),
-            user_name: Some(sender_name),
+            user_name: Some(sender_name.clone()),
             icon_url: None,
         }),
+
+        NotifDetail::ThreadUpdated { url, issue } => Some(NotifMessage {
+            text: format!("🔄 {}", issue_subject(web_base, issue, Some(url))),
+            user_name: None,
+            icon_url: None,
+        }),
+    }
+}
+
+// Generate digest messages listing notifications per repository and issue.
+// A long digest is split into several messages, each of which repeats the header.
+fn generate_digest(notifs: &[&Notification], web_base: &str) -> Vec<String> {
+    let header = format!(
+        "📰 Digest of {} notification{}",
+        notifs.len(),
+        if notifs.len() == 1 { "" } else { "s" }
+    );
+    let mut digest = DigestWriter::new(header);
+    for repo in digest::group(notifs) {
+        let repo_name = repo.repo_fullname.as_deref().unwrap_or("Others");
+        digest.start_repo(format!("*{}*", repo_name));
+        for thread in repo.threads {
+            match thread.issue {
+                Some(issue) => {
+                    let url = format!(
+                        "{}/{}/{}/pull/{}",
+                        web_base, &issue.repo.owner, &issue.repo.name, issue.number
+                    );
+                    digest.push(format!("• <{}|#{}> {}", url, issue.number, issue.title));
+                    for notif in thread.notifs {
+                        digest.push(format!("    {}", digest_line(notif)));
+                    }
+                }
+                None => {
+                    for notif in thread.notifs {
+                        digest.push(format!("• {}", digest_line(notif)));
+                    }
+                }
+            }
+        }
+    }
+    digest.messages
+}
+
+// Slack truncates a message text longer than 40,000 characters and recommends
+// keeping it under 4,000 characters.
+const MAX_DIGEST_CHARS: usize = 4000;
+
+// Packs digest lines into messages no longer than `MAX_DIGEST_CHARS`.
+struct DigestWriter {
+    header: String,
+    repo_line: String,
+    messages: Vec<String>,
+    // The length of the last message in characters.
+    len: usize,
+}
+
+impl DigestWriter {
+    fn new(header: String) -> Self {
+        DigestWriter {
+            len: header.chars().count(),
+            messages: vec![header.clone()],
+            header,
+            repo_line: String::new(),
+        }
+    }
+
+    fn start_repo(&mut self, repo_line: String) {
+        self.repo_line = repo_line.clone();
+        self.push(repo_line);
+    }
+
+    fn push(&mut self, line: String) {
+        let line = blocks::truncate(&line, MAX_DIGEST_CHARS / 4);
+        let line_len = line.chars().count();
+        if self.len + 1 + line_len > MAX_DIGEST_CHARS {
+            // Repeat the repository so that the continued lines make sense alone.
+            let mut message = format!("{} (continued)", self.header);
+            if line != self.repo_line {
+                message = format!("{}\n{}", message, self.repo_line);
+            }
+            self.len = message.chars().count();
+            self.messages.push(message);
+        }
+        let message = self.messages.last_mut().expect("messages are never empty");
+        message.push('\n');
+        message.push_str(&line);
+        self.len += 1 + line_len;
+    }
+}
+
+// Summarize a notification in one line for a digest.
+fn digest_line(notif: &Notification) -> String {
+    match &notif.detail {
+        NotifDetail::Unknown { sender, body } => {
+            let first_line = body.iter().find(|l| !l.trim().is_empty());
+            format!("{}: {}", sender, first_line.map_or("", |l| l.trim()))
+        }
+        NotifDetail::PrOpened { opener, .. } => format!("@{} opened", opener.login),
+        NotifDetail::PrReviewed {
+            url,
+            state,
+            commenter,
+            ..
+        } => format!(
+            "@{} <{}|reviewed> {}",
+            commenter.login,
+            url,
+            review_state_emoji(state)
+        ),
+        NotifDetail::PrReviewCommented { url, commenter, .. }
+        | NotifDetail::Commented { url, commenter, .. } => {
+            format!("@{} <{}|commented>", commenter.login, url)
+        }
+        NotifDetail::DirectReviewRequested { reviewee, .. } => {
+            format!("@{} requested your review", reviewee.login)
+        }
+        NotifDetail::TeamReviewRequested { reviewee, team, .. } => {
+            format!("@{} requested review from {}", reviewee.login, team)
+        }
+        NotifDetail::IssueClosed {
+            closer, is_merge, ..
+        } => {
+            let action = if *is_merge { "merged" } else { "closed" };
+            format!("@{} {}", closer.login, action)
+        }
+        NotifDetail::Pushed {
+            diff_url,
+            committer,
+            commits,
+            ..
+        } => format!(
+            "@{} pushed <{}|{} commit{}>",
+            committer.login,
+            diff_url,
+            commits.len(),
+            if commits.len() == 1 { "" } else { "s" }
+        ),
+        NotifDetail::WorkflowCancelled {
+            workflow_name,
+            result_url,
+            ..
+        } => format!("workflow cancelled: <{}|{}>", result_url, workflow_name),
+        NotifDetail::ThreadUpdated { url, .. } => format!("<{}|updated>", url),
     }
 }
 
-fn issue_subject(issue: &github::IssueInfo, title_link: Option<&str>) -> String {
+fn issue_subject(web_base: &str, issue: &github::IssueInfo, title_link: Option<&str>) -> String {
     let pr_url = format!(
-        "https://github.com/{}/{}/pull/{}",
-        &issue.repo.owner, &issue.repo.name, issue.number
+        "{}/{}/{}/pull/{}",
+        web_base, &issue.repo.owner, &issue.repo.name, issue.number
     );
     let title_link = title_link.map(|link| format!("<{}|{}>", link, &issue.title));
     let title = title_link.as_deref().unwrap_or(&issue.title);
@@ -211,6 +443,45 @@ fn issue_subject(issue: &github::IssueInfo, title_link: Option<&str>) -> String
     )
 }
 
+// Show the status of a PR in the line following its subject, if fetched.
+fn status_line(status: &Option<github::PrStatus>) -> String {
+    match status {
+        Some(status) => format!("\n{}", pr_status_summary(status)),
+        None => String::new(),
+    }
+}
+
+// Summarize the status of a PR in one line, such as "📝 Draft · ✅ CI · 👀 Review required · +10 -2".
+fn pr_status_summary(status: &github::PrStatus) -> String {
+    use github::{CheckState, MergeableState, ReviewDecision};
+
+    let mut parts = Vec::new();
+    if status.is_draft {
+        parts.push(String::from("📝 Draft"));
+    }
+    match status.checks {
+        Some(CheckState::Success) => parts.push(String::from("✅ CI")),
+        Some(CheckState::Failure | CheckState::Error) => parts.push(String::from("❌ CI")),
+        Some(CheckState::Pending | CheckState::Expected) => parts.push(String::from("⏳ CI")),
+        None => {}
+    }
+    match status.review_decision {
+        Some(ReviewDecision::Approved) => parts.push(String::from("👍 Approved")),
+        Some(ReviewDecision::ChangesRequested) => parts.push(String::from("🙅 Changes requested")),
+        Some(ReviewDecision::ReviewRequired) => parts.push(String::from("👀 Review required")),
+        None => {}
+    }
+    if status.mergeable == MergeableState::Conflicting {
+        parts.push(String::from("⚠️ Conflicts"));
+    }
+    parts.push(format!("+{} -{}", status.additions, status.deletions));
+    if !status.labels.is_empty() {
+        let labels = status.labels.iter().map(|l| format!("`{}`", l));
+        parts.push(labels.collect::<Vec<_>>().join(" "));
+    }
+    parts.join(" · ")
+}
+
 fn review_state_emoji(state: &github::ReviewState) -> &'static str {
     match *state {
         github::ReviewState::Commented => "💬",
@@ -232,3 +503,70 @@ fn join_commit_messages(commits: &[github::CommitInfo], max: usize) -> String {
     }
     joined_msg
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notif, testing};
+
+    fn pushed(repo: &str, n: usize) -> Notification {
+        Notification {
+            id: None,
+            reason: None,
+            detail: NotifDetail::Pushed {
+                diff_url: format!("https://github.com/acme/{}/pull/{}/files/abc", repo, n),
+                pr: github::IssueInfo {
+                    repo: github::Repository {
+                        owner: String::from("acme"),
+                        name: repo.to_string(),
+                    },
+                    number: n,
+                    title: "A long title to fill the digest ".repeat(4),
+                },
+                committer: github::User {
+                    login: String::from("alice"),
+                    avatar_url: String::new(),
+                },
+                commits: Vec::new(),
+                status: None,
+            },
+        }
+    }
+
+    #[test]
+    fn split_long_digest() {
+        let notifs = (1..=100).map(|n| pushed("widgets", n)).collect::<Vec<_>>();
+        let notifs = notifs.iter().collect::<Vec<_>>();
+        let messages = generate_digest(&notifs, "https://github.com");
+
+        assert!(messages.len() > 1);
+        assert!(messages
+            .iter()
+            .all(|m| m.chars().count() <= MAX_DIGEST_CHARS));
+        assert!(messages[0].starts_with("📰 Digest of 100 notifications\n*acme/widgets*"));
+        assert!(
+            messages[1].starts_with("📰 Digest of 100 notifications (continued)\n*acme/widgets*")
+        );
+        let items = messages
+            .iter()
+            .flat_map(|m| m.lines().filter(|l| l.starts_with('•')));
+        assert_eq!(items.count(), 100);
+    }
+
+    #[test]
+    fn show_pr_status_fetched_for_email() {
+        let path = testing::fixtures_dir("emails/direct_review_request.eml");
+        let email = crate::email::parse_message(&std::fs::read(path).unwrap()).unwrap();
+        let fake = testing::fake_github();
+        let github = fake.client("https://github.com");
+        let cx = notif::BuildContext { github: &github };
+        let notif = notif::build_notification(&cx, email).unwrap();
+
+        let message = generate_message(&notif, "https://github.com").unwrap();
+        let lines = message.text.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[1],
+            "✅ CI · 👀 Review required · +42 -7 · `enhancement`"
+        );
+    }
+}
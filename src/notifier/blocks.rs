@@ -0,0 +1,472 @@
+//! Renders notifications as Slack Block Kit messages.
+//! See https://api.slack.com/block-kit for the block types.
+
+use crate::{
+    github,
+    interactivity::{ACTION_IGNORE_PR, ACTION_MUTE_PR, ACTION_SNOOZE_PR},
+    notif::{NotifDetail, Notification},
+};
+use serde_json::{json, Value};
+
+// A text in a section block must be shorter than 3000 characters.
+const MAX_SECTION_CHARS: usize = 2900;
+
+struct Actor<'a> {
+    user: &'a github::User,
+    action: String,
+}
+
+pub fn render(
+    notif: &Notification,
+    web_base: &str,
+    alert: bool,
+    interactive: bool,
+) -> Option<Value> {
+    let detail = &notif.detail;
+    let mut blocks = match detail {
+        NotifDetail::Unknown { sender, body } => {
+            let mut blocks = vec![context(&[text_element(&format!("From {}", sender))])];
+            blocks.extend(text_section(&body.join("\n")));
+            blocks
+        }
+
+        NotifDetail::PrOpened { opener, pr, .. } => pr_blocks(
+            web_base,
+            pr,
+            None,
+            Actor {
+                user: opener,
+                action: String::from("opened this PR"),
+            },
+            None,
+        ),
+
+        NotifDetail::PrReviewed {
+            url,
+            pr,
+            state,
+            commenter,
+            comment,
+            ..
+        } => pr_blocks(
+            web_base,
+            pr,
+            Some(url),
+            Actor {
+                user: commenter,
+                action: format!("reviewed {}", super::review_state_emoji(state)),
+            },
+            Some(comment),
+        ),
+
+        NotifDetail::PrReviewCommented {
+            url,
+            pr,
+            commenter,
+            comment,
+            ..
+        } => pr_blocks(
+            web_base,
+            pr,
+            Some(url),
+            Actor {
+                user: commenter,
+                action: String::from("commented on the changes 💬"),
+            },
+            Some(comment),
+        ),
+
+        NotifDetail::DirectReviewRequested { reviewee, pr, .. } => pr_blocks(
+            web_base,
+            pr,
+            None,
+            Actor {
+                user: reviewee,
+                action: String::from("requested your review 🔔"),
+            },
+            None,
+        ),
+
+        NotifDetail::TeamReviewRequested { .. } => return None,
+
+        NotifDetail::IssueClosed {
+            closer,
+            issue,
+            is_merge,
+        } => {
+            let action = if *is_merge { "merged" } else { "closed" };
+            vec![
+                header(web_base, issue, None),
+                actor_context(&Actor {
+                    user: closer,
+                    action: String::from(action),
+                }),
+                issue_actions(web_base, issue, None, *is_merge),
+            ]
+        }
+
+        NotifDetail::Commented {
+            url,
+            issue,
+            commenter,
+            comment,
+        } => {
+            let mut blocks = vec![
+                header(web_base, issue, Some(url)),
+                actor_context(&Actor {
+                    user: commenter,
+                    action: String::from("commented 💬"),
+                }),
+            ];
+            blocks.extend(text_section(comment));
+            blocks.push(issue_actions(web_base, issue, Some(url), false));
+            blocks
+        }
+
+        NotifDetail::Pushed {
+            pr,
+            diff_url,
+            committer,
+            commits,
+            ..
+        } => {
+            let action = format!(
+                "pushed {} commit{}",
+                commits.len(),
+                if commits.len() == 1 { "" } else { "s" }
+            );
+            vec![
+                header(web_base, pr, None),
+                actor_context(&Actor {
+                    user: committer,
+                    action,
+                }),
+                section(&super::join_commit_messages(commits, 10)),
+                actions(vec![
+                    button("View diff", "view_diff", diff_url),
+                    button("Open PR", "open_issue", &issue_url(web_base, pr)),
+                ]),
+            ]
+        }
+
+        NotifDetail::WorkflowCancelled {
+            sender_name,
+            repo_fullname,
+            workflow_name,
+            result_url,
+        } => vec![
+            section(&format!(
+                "*[{}]* workflow cancelled: {}",
+                repo_fullname, workflow_name
+            )),
+            context(&[text_element(&format!("From {}", sender_name))]),
+            actions(vec![button("View result", "view_result", result_url)]),
+        ],
+
+        NotifDetail::ThreadUpdated { url, issue } => vec![
+            header(web_base, issue, Some(url)),
+            context(&[text_element("🔄 Updated")]),
+            issue_actions(web_base, issue, Some(url), false),
+        ],
+    };
+
+    // Every PR message starts with the header and the actor, followed by the status.
+    if let Some(status) = detail.pr_status() {
+        let summary = super::pr_status_summary(status);
+        blocks.insert(2, context(&[text_element(&summary)]));
+    }
+
+    if let Some(reason) = notif.reason {
+        blocks.push(context(&[text_element(&format!(
+            "_because {}_",
+            reason.description()
+        ))]));
+    }
+
+    // The buttons calling back into Gharry work only with the interactivity endpoint.
+    if let (true, Some(issue)) = (interactive, detail.issue()) {
+        let last_actions = blocks
+            .iter_mut()
+            .rev()
+            .find(|b| b["type"] == "actions")
+            .and_then(|b| b.get_mut("elements"));
+        if let Some(Value::Array(buttons)) = last_actions {
+            buttons.extend(interaction_buttons(issue, detail.pr().is_some()));
+        }
+    }
+    if alert {
+        blocks.push(section("<!here>"));
+    }
+    Some(Value::Array(blocks))
+}
+
+fn pr_blocks(
+    web_base: &str,
+    pr: &github::IssueInfo,
+    url: Option<&str>,
+    actor: Actor,
+    comment: Option<&str>,
+) -> Vec<Value> {
+    let mut blocks = vec![header(web_base, pr, url), actor_context(&actor)];
+    blocks.extend(comment.and_then(text_section));
+    blocks.push(issue_actions(web_base, pr, url, true));
+    blocks
+}
+
+fn issue_url(web_base: &str, issue: &github::IssueInfo) -> String {
+    format!(
+        "{}/{}/{}/pull/{}",
+        web_base, &issue.repo.owner, &issue.repo.name, issue.number
+    )
+}
+
+// Block Kit header blocks cannot contain links, so a section is used as a header.
+fn header(web_base: &str, issue: &github::IssueInfo, title_link: Option<&str>) -> Value {
+    section(&format!(
+        "*{}*",
+        super::issue_subject(web_base, issue, title_link)
+    ))
+}
+
+fn actor_context(actor: &Actor) -> Value {
+    context(&[
+        json!({
+            "type": "image",
+            "image_url": actor.user.avatar_url,
+            "alt_text": actor.user.login,
+        }),
+        text_element(&format!("*@{}* {}", actor.user.login, actor.action)),
+    ])
+}
+
+fn issue_actions(
+    web_base: &str,
+    issue: &github::IssueInfo,
+    url: Option<&str>,
+    is_pr: bool,
+) -> Value {
+    let issue_url = issue_url(web_base, issue);
+    let open_label = if is_pr { "Open PR" } else { "Open" };
+    let mut buttons = vec![button(open_label, "open_issue", url.unwrap_or(&issue_url))];
+    if is_pr {
+        buttons.push(button(
+            "View diff",
+            "view_diff",
+            &format!("{}/files", issue_url),
+        ));
+    }
+    actions(buttons)
+}
+
+fn section(text: &str) -> Value {
+    json!({
+        "type": "section",
+        "text": text_element(&truncate(text, MAX_SECTION_CHARS)),
+    })
+}
+
+// Slack rejects a section with an empty text, so a blank text gets no section.
+fn text_section(text: &str) -> Option<Value> {
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(section(text))
+    }
+}
+
+fn context(elements: &[Value]) -> Value {
+    json!({ "type": "context", "elements": elements })
+}
+
+fn actions(buttons: Vec<Value>) -> Value {
+    json!({ "type": "actions", "elements": buttons })
+}
+
+fn text_element(text: &str) -> Value {
+    json!({ "type": "mrkdwn", "text": text })
+}
+
+fn button(label: &str, action_id: &str, url: &str) -> Value {
+    json!({
+        "type": "button",
+        "text": { "type": "plain_text", "text": label },
+        "action_id": action_id,
+        "url": url,
+    })
+}
+
+// The values identify the PR as `owner/name#number`.
+// Muting and ignoring update the PR subscription on GitHub, so issues can only be snoozed.
+fn interaction_buttons(issue: &github::IssueInfo, is_pr: bool) -> Vec<Value> {
+    let value = format!("{}#{}", issue.repo.fullname(), issue.number);
+    let buttons: &[(&str, &str)] = if is_pr {
+        &[
+            ("Mute this PR", ACTION_MUTE_PR),
+            ("Ignore", ACTION_IGNORE_PR),
+            ("Snooze", ACTION_SNOOZE_PR),
+        ]
+    } else {
+        &[("Snooze", ACTION_SNOOZE_PR)]
+    };
+    buttons
+        .iter()
+        .map(|(label, action_id)| {
+            json!({
+                "type": "button",
+                "text": { "type": "plain_text", "text": label },
+                "action_id": action_id,
+                "value": value,
+            })
+        })
+        .collect()
+}
+
+pub(super) fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(login: &str) -> github::User {
+        github::User {
+            login: login.to_string(),
+            avatar_url: format!("https://avatars.example.com/{}", login),
+        }
+    }
+
+    fn pr() -> github::IssueInfo {
+        github::IssueInfo {
+            repo: github::Repository {
+                owner: String::from("acme"),
+                name: String::from("widgets"),
+            },
+            number: 42,
+            title: String::from("Add widgets"),
+        }
+    }
+
+    #[test]
+    fn render_pr_review() {
+        let detail = NotifDetail::PrReviewed {
+            url: String::from("https://github.com/acme/widgets/pull/42#pullrequestreview-1001"),
+            pr: pr(),
+            state: github::ReviewState::Approved,
+            commenter: user("bob"),
+            comment: String::from("LGTM"),
+            status: None,
+        };
+        let notif = Notification {
+            id: None,
+            detail,
+            reason: Some(github::NotificationReason::Author),
+        };
+        let blocks = render(&notif, "https://github.com", true, true).unwrap();
+        let blocks = blocks.as_array().unwrap();
+
+        let types = blocks.iter().map(|b| b["type"].as_str().unwrap());
+        assert_eq!(
+            types.collect::<Vec<_>>(),
+            ["section", "context", "section", "actions", "context", "section"]
+        );
+        assert_eq!(blocks[2]["text"]["text"], "LGTM");
+
+        let buttons = blocks[3]["elements"].as_array().unwrap();
+        let action_ids = buttons
+            .iter()
+            .map(|b| b["action_id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            action_ids,
+            [
+                "open_issue",
+                "view_diff",
+                ACTION_MUTE_PR,
+                ACTION_IGNORE_PR,
+                ACTION_SNOOZE_PR
+            ]
+        );
+        assert_eq!(buttons[2]["value"], "acme/widgets#42");
+    }
+
+    #[test]
+    fn only_snooze_button_for_issue() {
+        let notif = Notification {
+            id: None,
+            detail: NotifDetail::Commented {
+                url: String::from("https://github.com/acme/widgets/issues/7#issuecomment-1"),
+                issue: pr(),
+                commenter: user("bob"),
+                comment: String::from("Reproduced"),
+            },
+            reason: None,
+        };
+        let blocks = render(&notif, "https://github.com", false, true).unwrap();
+        let buttons = blocks[3]["elements"].as_array().unwrap();
+        let action_ids = buttons.iter().map(|b| b["action_id"].as_str().unwrap());
+        assert_eq!(
+            action_ids.collect::<Vec<_>>(),
+            ["open_issue", ACTION_SNOOZE_PR]
+        );
+    }
+
+    #[test]
+    fn skip_empty_comment() {
+        let notif = Notification {
+            id: None,
+            detail: NotifDetail::Commented {
+                url: String::from("https://github.com/acme/widgets/pull/42#issuecomment-1"),
+                issue: pr(),
+                commenter: user("bob"),
+                comment: String::from("\n  \n"),
+            },
+            reason: None,
+        };
+        let blocks = render(&notif, "https://github.com", false, false).unwrap();
+        let types = blocks.as_array().unwrap().iter().map(|b| &b["type"]);
+        assert_eq!(types.collect::<Vec<_>>(), ["section", "context", "actions"]);
+
+        let notif = Notification {
+            id: None,
+            detail: NotifDetail::Unknown {
+                sender: String::from("GitHub"),
+                body: vec![String::new()],
+            },
+            reason: None,
+        };
+        let blocks = render(&notif, "https://github.com", false, false).unwrap();
+        assert_eq!(blocks.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn render_pr_status_after_actor() {
+        let status = github::PrStatus {
+            is_draft: true,
+            checks: Some(github::CheckState::Failure),
+            review_decision: Some(github::ReviewDecision::ChangesRequested),
+            mergeable: github::MergeableState::Conflicting,
+            labels: vec![String::from("bug"), String::from("ui")],
+            additions: 120,
+            deletions: 30,
+        };
+        let notif = Notification {
+            id: None,
+            detail: NotifDetail::DirectReviewRequested {
+                reviewee: user("alice"),
+                pr: pr(),
+                status: Some(status),
+            },
+            reason: None,
+        };
+        let blocks = render(&notif, "https://github.com", false, false).unwrap();
+        assert_eq!(blocks[2]["type"], "context");
+        assert_eq!(
+            blocks[2]["elements"][0]["text"],
+            "📝 Draft · ❌ CI · 🙅 Changes requested · ⚠️ Conflicts · +120 -30 · `bug` `ui`"
+        );
+    }
+}
@@ -1,83 +1,633 @@
 use crate::{
     config::Config,
-    github, notif, notifier, slack,
+    digest::{self, DigestItem, DigestState},
+    dlq::Dlq,
+    email::Email,
+    github, notif, rules,
+    seen::{self, SeenState},
+    sink::{self, Sink},
+    snooze::{self, SnoozeState},
+    source::{self, Content, Source},
     store::{State, Store},
+    web::retry::{self, Backoff},
+    webhook::{Deliveries, Inbox},
 };
 use anyhow::Result;
-use std::{collections::HashSet, thread, time::Duration};
+use std::{
+    collections::HashSet,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, Instant},
+};
 
-pub fn run(config: &Config) -> Result<()> {
-    let state_filename = format!(".state-{}.json", config.slack.mail_channel_id);
-    let state_path = config.dir.join(state_filename);
+// Polling state of a profile.
+struct Profile<'a> {
+    config: &'a Config,
+    github: github::Client,
+    outputs: Outputs<'a>,
+    sources: Vec<(Box<dyn Source>, Store)>,
+    dlq: Dlq,
+    webhook: Option<(&'a Receiver<notif::Notification>, Store<Inbox>)>,
+    // A failing profile rests until this time so that it does not hammer the APIs.
+    backoff: Backoff,
+    resume_at: Option<Instant>,
+}
 
-    let mut store = Store::load(state_path, State::new)?;
-    log::info!("Start from state: {}", store.state);
+// The destinations of notifications shared by all the sources of a profile.
+struct Outputs<'a> {
+    sinks: Vec<Box<dyn Sink + 'a>>,
+    digest: Option<Store<DigestState>>,
+    seen: Store<SeenState>,
+}
 
-    let slack = slack::Client::new(slack::Credentials {
-        bot_token: config.slack.bot_token.clone(),
-    })?;
+// A notification to be delivered, with the source cursor to save after it.
+struct Pending {
+    notif: notif::Notification,
+    cursor: Option<String>,
+}
 
-    let github = github::Client::new(github::Credentials {
-        auth_token: config.github.auth_token.clone(),
-    })?;
+impl<'a> Profile<'a> {
+    fn new(config: &'a Config, deliveries: &'a Deliveries) -> Result<Self> {
+        let github = build_github(config)?;
 
-    loop {
-        let data = slack.conversations_history(slack::ConvHistoryParams {
-            channel: &config.slack.mail_channel_id,
-            // - We don't need to specify the valid ts; it is just a timestamp.
-            // - The result does not contain a message with the specified ts if exists.
-            oldest_ts: &store.state.last_ts,
-            limit: None,
-        })?;
+        let mut sources = Vec::new();
+        for source_config in &config.sources {
+            let source = source::build(source_config, &config.slack, &config.github)?;
+            let state_path = config.state_path(
+                &format!("state-{}.json", source.id()),
+                &format!(".state-{}.json", source.id()),
+            )?;
+            let store = Store::load(state_path, || Ok(State::default()))?;
+            log::info!(
+                "[{}] Start {} from state: {}",
+                config.profile,
+                source.id(),
+                store.state
+            );
+            sources.push((source, store));
+        }
+
+        let webhook = match deliveries.get(&config.profile) {
+            Some(receiver) => {
+                let path = config.profile_path("webhook.json")?;
+                let inbox = Store::load(path, || Ok(Inbox::default()))?;
+                Some((receiver, inbox))
+            }
+            None => None,
+        };
+
+        Ok(Profile {
+            config,
+            github,
+            outputs: Outputs::open(config)?,
+            sources,
+            dlq: Dlq::open(config)?,
+            webhook,
+            backoff: profile_backoff(),
+            resume_at: None,
+        })
+    }
 
-        if data.messages.is_empty() {
-            log::info!("No new notifications found");
-        } else {
-            log::info!("{} notifications found", data.messages.len());
-            let last_ts = filter_and_notify(&config, &slack, &github, data.messages)?;
-            store.update_state(State { last_ts })?;
+    fn poll(&mut self) -> Result<()> {
+        for (source, store) in &mut self.sources {
+            let batch = source.fetch(store.state.cursor.as_deref())?;
+            let (messages, batch_cursor) = (batch.messages, batch.cursor);
+
+            if messages.is_empty() {
+                log::info!("[{}] No new notifications found", self.config.profile);
+            } else {
+                log::info!(
+                    "[{}] {} notifications found",
+                    self.config.profile,
+                    messages.len()
+                );
+                let pendings = build_pendings(
+                    &self.github,
+                    &mut self.outputs.seen,
+                    &source.id(),
+                    &self.dlq,
+                    messages,
+                )?;
+                filter_and_notify(
+                    self.config,
+                    &self.github,
+                    &mut self.outputs,
+                    load_snoozes(self.config)?.as_ref(),
+                    pendings,
+                    Some(store),
+                )?;
+            }
+
+            if store.state.cursor.as_deref() != Some(batch_cursor.as_str()) {
+                store.update_state_with(|state| state.cursor = Some(batch_cursor))?;
+            }
+            source.ack()?;
+        }
+
+        if let Some((receiver, inbox)) = &mut self.webhook {
+            let received = receiver.try_iter().collect::<Vec<_>>();
+            if !received.is_empty() {
+                log::info!(
+                    "[{}] {} notifications received by webhooks",
+                    self.config.profile,
+                    received.len()
+                );
+                inbox.update_state_with(|state| state.notifs.extend(received))?;
+            }
+            // Keep the notifications until they are delivered, so that a sink failure
+            // does not lose them. The delivered ones are skipped on retries by their IDs.
+            if !inbox.state.notifs.is_empty() {
+                let pendings = inbox.state.notifs.iter().map(|notif| Pending {
+                    notif: notif.clone(),
+                    cursor: None,
+                });
+                filter_and_notify(
+                    self.config,
+                    &self.github,
+                    &mut self.outputs,
+                    load_snoozes(self.config)?.as_ref(),
+                    pendings.collect(),
+                    None,
+                )?;
+                inbox.update_state_with(|state| state.notifs.clear())?;
+            }
+        }
+        self.send_digest_if_due()
+    }
+
+    fn send_digest_if_due(&mut self) -> Result<()> {
+        let (store, schedule) = match (&mut self.outputs.digest, &self.config.digest) {
+            (Some(store), Some(digest)) => (store, digest.schedule),
+            _ => return Ok(()),
+        };
+        if store.state.outbox.is_empty() {
+            if !store.state.is_due(schedule) {
+                return Ok(());
+            }
+            store.update_state_with(|state| {
+                state.outbox = mem::take(&mut state.items);
+                state.last_sent = digest::now();
+            })?;
+            if store.state.outbox.is_empty() {
+                return Ok(());
+            }
+            log::info!(
+                "[{}] Sending digest of {} notifications",
+                self.config.profile,
+                store.state.outbox.len()
+            );
         }
 
+        // Record the progress of each sink so that a retry does not repeat delivered messages.
+        for sink in self.outputs.sinks.iter_mut() {
+            let mut sent = store.state.sent.get(sink.name()).copied().unwrap_or(0);
+            let result = sink.send_digest(&store.state.outbox, &mut sent);
+            store.update_state_with(|state| {
+                state.sent.insert(sink.name().to_string(), sent);
+            })?;
+            result?;
+        }
+        store.update_state_with(|state| {
+            state.outbox.clear();
+            state.sent.clear();
+        })
+    }
+}
+
+impl<'a> Outputs<'a> {
+    fn open(config: &'a Config) -> Result<Self> {
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|s| sink::build(*s, config))
+            .collect::<Result<Vec<_>>>()?;
+
+        let digest = match &config.digest {
+            Some(_) => {
+                let path = config
+                    .state_path("digest.json", &format!(".digest-{}.json", config.profile))?;
+                let store = Store::load(path, || Ok(DigestState::new()))?;
+                log::info!(
+                    "[{}] {} notifications queued for digest",
+                    config.profile,
+                    store.state.items.len()
+                );
+                Some(store)
+            }
+            None => None,
+        };
+
+        let seen = Store::load(seen::store_path(config)?, || Ok(SeenState::default()))?;
+
+        Ok(Outputs {
+            sinks,
+            digest,
+            seen,
+        })
+    }
+}
+
+fn build_github(config: &Config) -> Result<github::Client> {
+    github::Client::new(
+        github::Credentials {
+            auth_token: config.github.auth_token.clone(),
+        },
+        config.github.endpoints.clone(),
+    )?
+    .with_cache_file(config.state_path(
+        "github-cache.json",
+        &format!(".github-cache-{}.json", config.profile),
+    )?)
+}
+
+pub fn run(configs: &[Config], deliveries: &Deliveries) -> Result<()> {
+    let mut profiles = configs
+        .iter()
+        .map(|config| Profile::new(config, deliveries))
+        .collect::<Result<Vec<_>>>()?;
+
+    loop {
+        poll_each(&mut profiles);
         log::info!("Finished so wait a while...");
         thread::sleep(Duration::from_secs(10));
     }
 }
 
-fn filter_and_notify(
+// A failure of a profile, such as an expired token, does not stop the other profiles.
+fn poll_each(profiles: &mut [Profile]) {
+    for profile in profiles {
+        if profile.resume_at.is_some_and(|at| Instant::now() < at) {
+            continue;
+        }
+        let result = profile.poll();
+        // The lookups cached until a failure are still valid.
+        if let Err(err) = profile.github.save_cache() {
+            log::warn!(
+                "[{}] Failed to save GitHub cache: {:#}",
+                profile.config.profile,
+                err
+            );
+        }
+        match result {
+            Ok(()) => {
+                profile.backoff.reset();
+                profile.resume_at = None;
+            }
+            Err(err) => {
+                // Rest at least as long as the server asked, such as until a rate limit resets.
+                let delay = profile.backoff.next_delay();
+                let delay = retry::retry_after(&err).map_or(delay, |d| d.max(delay));
+                log::error!("[{}] Polling failed: {:#}", profile.config.profile, err);
+                log::info!(
+                    "[{}] Will retry polling after {} seconds...",
+                    profile.config.profile,
+                    delay.as_secs()
+                );
+                profile.resume_at = Some(Instant::now() + delay);
+            }
+        }
+    }
+}
+
+fn profile_backoff() -> Backoff {
+    Backoff::new(Duration::from_secs(30), Duration::from_secs(30 * 60))
+}
+
+// Build notifications from fetched messages. An email which fails to be parsed is put into
+// the DLQ and skipped afterwards, while other errors are returned as is.
+fn build_pendings(
+    github: &github::Client,
+    seen: &mut Store<SeenState>,
+    source_id: &str,
+    dlq: &Dlq,
+    messages: Vec<source::Message>,
+) -> Result<Vec<Pending>> {
+    let now = digest::now();
+    let dead_letters = dlq.cursors(source_id)?;
+    let cx = notif::BuildContext { github };
+
+    // Read all the emails first so that their lookups are prefetched at once.
+    let mut contents = Vec::new();
+    for source::Message { cursor, content } in messages {
+        if dead_letters.contains(&cursor) {
+            log::info!("Skip dead letter at {}", cursor);
+            continue;
+        }
+        let content = match content {
+            Content::Email(email) => {
+                let raw_email = email.clone();
+                match catch_panic(|| notif::Draft::read(&cx, email)) {
+                    Ok(draft) => Read::Email(draft),
+                    Err(err) => {
+                        put_into_dlq(dlq, source_id, &cursor, raw_email, err)?;
+                        continue;
+                    }
+                }
+            }
+            Content::Notif(notif) => Read::Notif(notif),
+        };
+        contents.push((cursor, content));
+    }
+    notif::prefetch(
+        &cx,
+        contents.iter().filter_map(|(_, content)| match content {
+            Read::Email(draft) => Some(draft),
+            Read::Notif(_) => None,
+        }),
+    );
+
+    let mut pendings = Vec::new();
+    for (cursor, content) in contents {
+        let notif = match content {
+            Read::Email(draft) => {
+                let raw_email = draft.email.clone();
+                match catch_panic(|| draft.build(&cx)) {
+                    Ok(notif) => {
+                        if let Some(issue) = notif.detail.issue() {
+                            seen.update_state_with(|state| state.mark_emailed(issue, now))?;
+                        }
+                        notif
+                    }
+                    Err(err) => {
+                        put_into_dlq(dlq, source_id, &cursor, raw_email, err)?;
+                        continue;
+                    }
+                }
+            }
+            Read::Notif(notif) => {
+                if is_notified_by_email(&seen.state, &notif, now) {
+                    continue;
+                }
+                notif
+            }
+        };
+        pendings.push(Pending {
+            notif,
+            cursor: Some(cursor),
+        });
+    }
+    github.clear_prefetched();
+    Ok(pendings)
+}
+
+// A message read before building its notification.
+enum Read {
+    Email(notif::Draft),
+    Notif(notif::Notification),
+}
+
+// Only the emails which cannot be parsed are put into the DLQ. Other failures such as
+// API errors are returned to abort the cycle so that the email is processed again later.
+fn put_into_dlq(
+    dlq: &Dlq,
+    source_id: &str,
+    cursor: &str,
+    email: Email,
+    err: anyhow::Error,
+) -> Result<()> {
+    if !err.chain().any(|cause| cause.is::<notif::ParseError>()) {
+        return Err(err);
+    }
+    log::error!("Failed to parse email {:?}: {:#}", email.subject, err);
+    let dlq_id = dlq.push(source_id, cursor, email, &err)?;
+    log::info!("Put the email into DLQ: {}", dlq_id);
+    Ok(())
+}
+
+// Parsers may panic on an unexpected email layout. Turn it into an error
+// so that the email is put into the dead letters instead of crashing every run.
+fn catch_panic<T>(parse: impl FnOnce() -> Result<T>) -> Result<T> {
+    match panic::catch_unwind(AssertUnwindSafe(parse)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(notif::ParseError::Panicked { message }.into())
+        }
+    }
+}
+
+// Parse the dead letters again and deliver them in the same way as polled ones, so that
+// the delivered and snoozed notifications are filtered out. The succeeded entries are removed
+// and the failed ones are kept with the new errors.
+pub fn retry_dead_letters(config: &Config, dlq: &Dlq, ids: &[&str]) -> Result<()> {
+    let github = build_github(config)?;
+    let mut outputs = Outputs::open(config)?;
+    let result = retry(config, &github, &mut outputs, dlq, ids);
+    github.save_cache()?;
+    result
+}
+
+fn retry(
     config: &Config,
-    slack: &slack::Client,
     github: &github::Client,
-    mut messages: Vec<slack::Message>,
-) -> Result<String> {
-    // The messages are sorted by newest to oldest so
-    // we reverse the order to process them from oldest.
-    messages.reverse();
+    outputs: &mut Outputs,
+    dlq: &Dlq,
+    ids: &[&str],
+) -> Result<()> {
+    let snoozes = load_snoozes(config)?;
+    for id in ids {
+        let entry = dlq.get(id)?;
+        let cx = notif::BuildContext { github };
+        let notif = match catch_panic(|| notif::build_notification(&cx, entry.email)) {
+            Ok(notif) => notif,
+            Err(err) => {
+                log::error!("Failed to parse {} again: {:#}", id, err);
+                dlq.update_errors(id, &err)?;
+                continue;
+            }
+        };
+        if let Some(issue) = notif.detail.issue() {
+            let now = digest::now();
+            outputs
+                .seen
+                .update_state_with(|state| state.mark_emailed(issue, now))?;
+        }
+        let pending = Pending {
+            notif,
+            cursor: None,
+        };
+        filter_and_notify(
+            config,
+            github,
+            outputs,
+            snoozes.as_ref(),
+            vec![pending],
+            None,
+        )?;
+        dlq.remove(id)?;
+        log::info!("Forwarded {}", id);
+    }
+    Ok(())
+}
+
+// Check if a notification from the GitHub API is already notified by an email.
+fn is_notified_by_email(seen: &SeenState, notif: &notif::Notification, now: u64) -> bool {
+    match notif.detail.issue() {
+        Some(issue) if seen.is_emailed(issue, now) => {
+            log::info!(
+                "Skip notification of {}#{}: already notified by email",
+                issue.repo.fullname(),
+                issue.number
+            );
+            true
+        }
+        _ => false,
+    }
+}
 
-    let last_ts = messages[messages.len() - 1].ts.clone();
+// The snoozes are updated by the interactivity server in another thread.
+fn load_snoozes(config: &Config) -> Result<Option<SnoozeState>> {
+    match config.interactivity {
+        Some(_) => {
+            let store = Store::load(snooze::store_path(config)?, || Ok(SnoozeState::default()))?;
+            Ok(Some(store.state))
+        }
+        None => Ok(None),
+    }
+}
 
-    let notifs = notif::build_notifications(notif::BuildContext { github }, messages)?;
-    let unsubscribed = unsubscribe_undesired_notifs(github, &notifs, &config.github.login_name)?;
+fn filter_and_notify(
+    config: &Config,
+    github: &github::Client,
+    outputs: &mut Outputs,
+    snoozes: Option<&SnoozeState>,
+    mut pendings: Vec<Pending>,
+    mut checkpoint: Option<&mut Store>,
+) -> Result<()> {
+    let seen = &mut outputs.seen;
+    let mut batch_ids = HashSet::new();
+    pendings.retain(|p| match &p.notif.id {
+        Some(id) if seen.state.contains(id) || !batch_ids.insert(id.clone()) => {
+            log::info!("Skip notification already delivered: {}", id);
+            false
+        }
+        _ => true,
+    });
+    if let Some(snoozes) = snoozes {
+        let now = digest::now();
+        pendings.retain(|p| match p.notif.detail.issue() {
+            Some(issue) if snoozes.is_snoozed(issue, now) => {
+                log::info!(
+                    "Drop notification of snoozed {}#{}",
+                    issue.repo.fullname(),
+                    issue.number
+                );
+                false
+            }
+            _ => true,
+        });
+    }
+    let (notifs, cursors): (Vec<_>, Vec<_>) =
+        pendings.into_iter().map(|p| (p.notif, p.cursor)).unzip();
+    let decisions = notifs
+        .iter()
+        .map(|notif| config.rules.decide(notif))
+        .collect::<Vec<_>>();
+    let unsubscribed =
+        unsubscribe_undesired_notifs(github, &notifs, &decisions, &config.github.login_name)?;
 
-    for (idx, notif) in notifs.into_iter().enumerate() {
-        if !unsubscribed.contains(&idx) {
-            notifier::notify_by_slack(slack, &config.slack.dest_channel_id, notif)?;
-            thread::sleep(Duration::from_secs(1));
+    // Save the progress as soon as each notification is handled, so that a retry after
+    // a failure midway neither delivers the preceding ones again nor parses them again.
+    let mut queued = 0;
+    let items = notifs.into_iter().zip(decisions).zip(cursors);
+    for (idx, ((notif, decision), cursor)) in items.enumerate() {
+        let id = notif.id.clone();
+        match (decision.delivery, outputs.digest.as_mut()) {
+            (rules::Delivery::Drop, _) => log::info!("Drop notification by rule"),
+            (rules::Delivery::Digest, Some(store)) => {
+                let item = DigestItem {
+                    notif,
+                    channel: decision.channel,
+                };
+                store.update_state_with(|state| state.items.push(item))?;
+                queued += 1;
+            }
+            _ if unsubscribed.contains(&idx) => {}
+            _ => send_to_sinks(outputs, &notif, &decision)?,
+        }
+        if let Some(id) = id {
+            outputs.seen.update_state_with(|state| state.insert(id))?;
+        }
+        if let (Some(store), Some(cursor)) = (checkpoint.as_mut(), cursor) {
+            store.update_state_with(|state| state.cursor = Some(cursor))?;
         }
     }
+    if queued > 0 {
+        log::info!("{} notifications queued for digest", queued);
+    }
 
-    Ok(last_ts)
+    Ok(())
+}
+
+// Send a notification to every sink even if some of them fail. The sinks which delivered it
+// are recorded by its ID so that a retry does not repeat them, as digests do.
+fn send_to_sinks(
+    outputs: &mut Outputs,
+    notif: &notif::Notification,
+    decision: &rules::Decision,
+) -> Result<()> {
+    let id = notif.id.as_ref();
+    let mut sent = id
+        .and_then(|id| outputs.seen.state.partially_sent.get(id))
+        .cloned()
+        .unwrap_or_default();
+    let mut failure = None;
+    for sink in outputs.sinks.iter_mut() {
+        if sent.iter().any(|name| name == sink.name()) {
+            continue;
+        }
+        match sink.send(notif, decision) {
+            Ok(()) => sent.push(sink.name().to_string()),
+            Err(err) => {
+                let err = err.context(format!("failed to send notification to {}", sink.name()));
+                match failure {
+                    None => failure = Some(err),
+                    Some(_) => log::error!("{:#}", err),
+                }
+            }
+        }
+    }
+    match (failure, id) {
+        (None, _) => Ok(()),
+        (Some(err), Some(id)) => {
+            outputs.seen.update_state_with(|state| {
+                state.partially_sent.insert(id.clone(), sent);
+            })?;
+            Err(err)
+        }
+        (Some(err), None) => Err(err),
+    }
 }
 
 fn unsubscribe_undesired_notifs(
     github: &github::Client,
     notifs: &[notif::Notification],
+    decisions: &[rules::Decision],
     user_login: &str,
 ) -> Result<HashSet<usize>> {
+    // Look through the whole batch before unsubscribing. When we process a backlog of emails,
+    // the user may be mentioned in a PR after its team review request. Unsubscribing the PR
+    // in that case would lose the following notifications of it.
+    let involved_issues = notifs
+        .iter()
+        .filter(|notif| is_involved(notif))
+        .filter_map(|notif| notif.detail.issue())
+        .map(|issue| (issue.repo.fullname(), issue.number))
+        .collect::<HashSet<_>>();
+
     let targets = notifs
         .iter()
+        .zip(decisions)
         .enumerate()
-        .filter_map(|(idx, notif)| match notif.detail {
-            notif::NotifDetail::TeamReviewRequested { ref pr, .. } => Some((idx, pr)),
+        .filter_map(|(idx, (notif, decision))| match decision.delivery {
+            // Only PRs have subscriptions to update by review requests.
+            rules::Delivery::Unsubscribe => notif.detail.pr().map(|pr| (idx, pr)),
             _ => None,
         })
         .collect::<Vec<_>>();
@@ -86,6 +636,14 @@ fn unsubscribe_undesired_notifs(
 
     let mut unsubscribed = HashSet::new();
     for (idx, pr) in targets {
+        if involved_issues.contains(&(pr.repo.fullname(), pr.number)) {
+            log::info!(
+                "skip unsubscribing {}#{}: you are involved in it",
+                pr.repo.fullname(),
+                pr.number
+            );
+            continue;
+        }
         log::debug!("unsubscribing {}#{}...", pr.repo.fullname(), pr.number);
         let done = github.unsubscribe_pr(&github::UnsubscribePrParams {
             repo: &pr.repo,
@@ -98,9 +656,438 @@ fn unsubscribe_undesired_notifs(
     }
 
     Ok(unsubscribed)
+}
+
+// Check if the notification shows the user takes part in the issue personally.
+fn is_involved(notif: &notif::Notification) -> bool {
+    use github::NotificationReason::*;
+    matches!(
+        notif.reason,
+        Some(Mention | Author | Assign | Comment | Manual)
+    ) || notif.detail.kind() == notif::NotifKind::DirectReviewRequested
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        github::fake::FakeGitHub, sink::fake::FakeSink, source::fake::FakeSource, testing,
+    };
+    use std::sync::mpsc;
+
+    fn load_email(name: &str) -> Email {
+        let path = testing::fixtures_dir(format!("emails/{}.eml", name));
+        crate::email::parse_message(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    // A profile which reads the emails from a fake source and sends notifications to the sink.
+    fn profile<'a>(
+        config: &'a Config,
+        github: github::Client,
+        emails: Vec<Email>,
+        sink: &FakeSink,
+    ) -> Profile<'a> {
+        let store = Store::load(config.dir.join(".state-fake.json"), || Ok(State::default()));
+        let seen = Store::load(seen::store_path(config).unwrap(), || {
+            Ok(SeenState::default())
+        });
+        Profile {
+            config,
+            github,
+            outputs: Outputs {
+                sinks: vec![Box::new(sink.clone())],
+                digest: None,
+                seen: seen.unwrap(),
+            },
+            sources: vec![(Box::new(FakeSource::new("fake", emails)), store.unwrap())],
+            dlq: Dlq::open(config).unwrap(),
+            webhook: None,
+            backoff: profile_backoff(),
+            resume_at: None,
+        }
+    }
+
+    // The variant names of the delivered notifications.
+    fn sent_kinds(sink: &FakeSink) -> Vec<String> {
+        let log = sink.log();
+        let details = log.sent.iter().filter_map(|n| n["detail"].as_object());
+        details.filter_map(|d| d.keys().next().cloned()).collect()
+    }
+
+    fn team_review_request() -> notif::Notification {
+        notif::Notification {
+            id: Some(String::from("slack:1622543400.000100")),
+            detail: notif::NotifDetail::TeamReviewRequested {
+                reviewee: user("alice"),
+                pr: issue(),
+                team: String::from("acme/reviewers"),
+            },
+            reason: Some(github::NotificationReason::ReviewRequested),
+        }
+    }
+
+    fn mention() -> notif::Notification {
+        notif::Notification {
+            id: Some(String::from("github:issuecomment-4004")),
+            detail: notif::NotifDetail::Commented {
+                url: String::from("https://github.com/acme/widgets/pull/42#issuecomment-4004"),
+                issue: issue(),
+                commenter: user("bob"),
+                comment: String::from("@ryym could you take a look?"),
+            },
+            reason: Some(github::NotificationReason::Mention),
+        }
+    }
+
+    fn user(login: &str) -> github::User {
+        github::User {
+            login: login.to_string(),
+            avatar_url: String::new(),
+        }
+    }
+
+    fn issue() -> github::IssueInfo {
+        github::IssueInfo {
+            repo: github::Repository {
+                owner: String::from("acme"),
+                name: String::from("widgets"),
+            },
+            number: 42,
+            title: String::from("Add widgets"),
+        }
+    }
+
+    fn unsubscribe(notifs: &[notif::Notification]) -> HashSet<usize> {
+        let fake = testing::fake_github();
+        let rules = rules::RuleSet::new(Vec::new(), &[]);
+        let decisions = notifs.iter().map(|n| rules.decide(n)).collect::<Vec<_>>();
+        unsubscribe_undesired_notifs(
+            &fake.client("https://github.com"),
+            notifs,
+            &decisions,
+            "ryym",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn deliver_fetched_emails_to_sinks() {
+        let config = Config::for_test(testing::temp_dir("poll"), "");
+        let fake = testing::fake_github();
+        let sink = FakeSink::default();
+        let emails = vec![load_email("push"), load_email("issue_comment")];
+        let mut profile = profile(&config, fake.client("https://github.com"), emails, &sink);
+
+        profile.poll().unwrap();
+        assert_eq!(sent_kinds(&sink), ["Pushed", "Commented"]);
+        assert_eq!(profile.sources[0].1.state.cursor.as_deref(), Some("2"));
+
+        // Nothing is delivered again.
+        profile.poll().unwrap();
+        assert_eq!(sink.log().sent.len(), 2);
+    }
+
+    #[test]
+    fn retry_emails_after_sink_failure() {
+        let config = Config::for_test(testing::temp_dir("sink-failure"), "");
+        let fake = testing::fake_github();
+        let sink = FakeSink::default();
+        let emails = vec![load_email("push")];
+        let mut profile = profile(&config, fake.client("https://github.com"), emails, &sink);
 
-    // TODO: reason が mention な通知が来てたら unsubscribe しないようにする。
-    // 常に最新の通知を処理してれば大抵は unsubscribe -> メンション通知という順序なので問題ないが、
-    // 溜まった通知メールを一気に処理するようなケースでは、メンション通知を受け取ってるのに
-    // 後から unsubscribe してしまいそれ以降の通知を受け取れなくなる、という事が起きえる。
+        sink.set_down(true);
+        assert!(profile.poll().is_err());
+        assert_eq!(profile.sources[0].1.state.cursor, None);
+
+        sink.set_down(false);
+        profile.poll().unwrap();
+        assert_eq!(sent_kinds(&sink), ["Pushed"]);
+        assert_eq!(profile.sources[0].1.state.cursor.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn resend_only_to_failed_sink() {
+        let config = Config::for_test(testing::temp_dir("sink-partial-failure"), "");
+        let fake = testing::fake_github();
+        let (sink, broken_sink) = (FakeSink::named("a"), FakeSink::named("b"));
+        let mut email = load_email("push");
+        email.id = Some(String::from("push@github.com"));
+        let mut profile = profile(
+            &config,
+            fake.client("https://github.com"),
+            vec![email],
+            &sink,
+        );
+        profile.outputs.sinks.push(Box::new(broken_sink.clone()));
+
+        broken_sink.set_down(true);
+        assert!(profile.poll().is_err());
+        assert_eq!(sent_kinds(&sink), ["Pushed"]);
+        assert_eq!(profile.sources[0].1.state.cursor, None);
+
+        broken_sink.set_down(false);
+        profile.poll().unwrap();
+        assert_eq!(sent_kinds(&sink), ["Pushed"]);
+        assert_eq!(sent_kinds(&broken_sink), ["Pushed"]);
+        let seen = &profile.outputs.seen.state;
+        assert!(seen.contains("push@github.com") && seen.partially_sent.is_empty());
+    }
+
+    #[test]
+    fn keep_webhook_notifs_until_delivered() {
+        let config = Config::for_test(testing::temp_dir("webhook-inbox"), "");
+        let fake = testing::fake_github();
+        let sink = FakeSink::default();
+        let (sender, receiver) = mpsc::channel();
+        let mut profile = profile(&config, fake.client("https://github.com"), vec![], &sink);
+        let inbox = Store::load(config.dir.join("webhook.json"), || Ok(Inbox::default()));
+        profile.webhook = Some((&receiver, inbox.unwrap()));
+        sender.send(mention()).unwrap();
+
+        sink.set_down(true);
+        assert!(profile.poll().is_err());
+        assert_eq!(profile.webhook.as_ref().unwrap().1.state.notifs.len(), 1);
+
+        sink.set_down(false);
+        profile.poll().unwrap();
+        assert_eq!(sent_kinds(&sink), ["Commented"]);
+        assert!(profile.webhook.as_ref().unwrap().1.state.notifs.is_empty());
+    }
+
+    #[test]
+    fn isolate_failure_of_profile() {
+        let configs = [
+            Config::for_test(testing::temp_dir("profile-a"), ""),
+            Config::for_test(testing::temp_dir("profile-b"), ""),
+        ];
+        let fake = testing::fake_github();
+        let (broken_sink, sink) = (FakeSink::default(), FakeSink::default());
+        broken_sink.set_down(true);
+        let mut profiles = [
+            profile(
+                &configs[0],
+                fake.client("https://github.com"),
+                vec![load_email("push")],
+                &broken_sink,
+            ),
+            profile(
+                &configs[1],
+                fake.client("https://github.com"),
+                vec![load_email("push")],
+                &sink,
+            ),
+        ];
+
+        poll_each(&mut profiles);
+        assert!(profiles[0].resume_at.is_some());
+        assert!(profiles[1].resume_at.is_none());
+        assert_eq!(sent_kinds(&sink), ["Pushed"]);
+
+        // The failing profile rests until the backoff delay passes.
+        broken_sink.set_down(false);
+        poll_each(&mut profiles);
+        assert!(broken_sink.log().sent.is_empty());
+    }
+
+    #[test]
+    fn resume_digest_after_sink_failure() {
+        let config = Config::for_test(
+            testing::temp_dir("digest-failure"),
+            "[digest]\nschedule = \"hourly\"\n",
+        );
+        let fake = testing::fake_github();
+        let (sink, broken_sink) = (FakeSink::named("a"), FakeSink::named("b"));
+        let mut profile = profile(&config, fake.client("https://github.com"), vec![], &sink);
+        profile.outputs.sinks.push(Box::new(broken_sink.clone()));
+        let path = config.dir.join("digest.json");
+        let mut state = DigestState::new();
+        state.last_sent = 0;
+        state.items.push(DigestItem {
+            notif: team_review_request(),
+            channel: None,
+        });
+        profile.outputs.digest = Some(Store::load(path, || Ok(state)).unwrap());
+
+        broken_sink.set_down(true);
+        assert!(profile.send_digest_if_due().is_err());
+        assert_eq!(sink.log().digests.len(), 1);
+
+        // Only the failed sink sends the digest on retry.
+        broken_sink.set_down(false);
+        profile.send_digest_if_due().unwrap();
+        assert_eq!(sink.log().digests.len(), 1);
+        assert_eq!(broken_sink.log().digests.len(), 1);
+        let state = &profile.outputs.digest.as_ref().unwrap().state;
+        assert!(state.outbox.is_empty() && state.items.is_empty());
+    }
+
+    #[test]
+    fn unsubscribe_team_review_request() {
+        let unsubscribed = unsubscribe(&[team_review_request()]);
+        assert_eq!(unsubscribed, HashSet::from([0]));
+    }
+
+    #[test]
+    fn keep_subscription_if_mentioned_in_batch() {
+        let unsubscribed = unsubscribe(&[team_review_request(), mention()]);
+        assert!(unsubscribed.is_empty());
+    }
+
+    #[test]
+    fn unsubscribe_only_prs() {
+        let issue_comment = notif::Notification {
+            id: None,
+            detail: notif::NotifDetail::Commented {
+                url: String::from("https://github.com/acme/widgets/issues/42#issuecomment-4004"),
+                issue: issue(),
+                commenter: user("bob"),
+                comment: String::from("Thanks!"),
+            },
+            reason: None,
+        };
+        let decision = rules::Decision {
+            delivery: rules::Delivery::Unsubscribe,
+            channel: None,
+        };
+        let fake = testing::fake_github();
+        let unsubscribed = unsubscribe_undesired_notifs(
+            &fake.client("https://github.com"),
+            &[issue_comment],
+            &[decision],
+            "ryym",
+        )
+        .unwrap();
+        assert!(unsubscribed.is_empty());
+        assert!(fake.requests().is_empty());
+    }
+
+    #[test]
+    fn skip_api_notifs_already_notified_by_email() {
+        let thread_updated = |number| notif::Notification {
+            id: None,
+            detail: notif::NotifDetail::ThreadUpdated {
+                url: String::new(),
+                issue: github::IssueInfo { number, ..issue() },
+            },
+            reason: None,
+        };
+        let mut seen = SeenState::default();
+        seen.mark_emailed(&issue(), 0);
+        assert!(is_notified_by_email(&seen, &thread_updated(42), 60));
+        assert!(!is_notified_by_email(&seen, &thread_updated(43), 60));
+    }
+
+    #[test]
+    fn put_unparsable_email_into_dead_letters() {
+        let dir = testing::temp_dir("dead-letters");
+        let dlq = Dlq::open_dir(dir.join("dlq")).unwrap();
+
+        let mut broken = load_email("push");
+        broken.subject = broken.subject.replace("#42", "#99999999999999999999");
+        let messages = |first: Email| {
+            vec![
+                source::Message {
+                    cursor: String::from("1"),
+                    content: Content::Email(first),
+                },
+                source::Message {
+                    cursor: String::from("2"),
+                    content: Content::Email(load_email("issue_comment")),
+                },
+            ]
+        };
+
+        let mut seen = Store::load(dir.join("seen.json"), || Ok(SeenState::default())).unwrap();
+        let fake = testing::fake_github();
+        let github = fake.client("https://github.com");
+
+        let pendings = build_pendings(&github, &mut seen, "test", &dlq, messages(broken)).unwrap();
+        let cursors = pendings
+            .iter()
+            .map(|p| p.cursor.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(cursors, [Some("2")]);
+        let entries = dlq.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (entries[0].source.as_str(), entries[0].cursor.as_str()),
+            ("test", "1")
+        );
+        assert!(entries[0].errors[0].contains("invalid issue number"));
+        assert!(entries[0].email.text_body.contains("pushed 2 commits"));
+
+        // The dead letter is skipped on retries even if it could be parsed now.
+        let pendings = build_pendings(
+            &github,
+            &mut seen,
+            "test",
+            &dlq,
+            messages(load_email("push")),
+        )
+        .unwrap();
+        assert_eq!(pendings.len(), 1);
+        assert_eq!(dlq.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retry_dead_letters_through_filters() {
+        let config = Config::for_test(testing::temp_dir("dlq-retry"), "");
+        let sink = FakeSink::default();
+        let fake = testing::fake_github();
+        let mut profile = profile(&config, fake.client("https://github.com"), vec![], &sink);
+        profile
+            .outputs
+            .seen
+            .update_state_with(|state| state.insert(String::from("github:issuecomment-4004")))
+            .unwrap();
+
+        let err = anyhow::anyhow!("parser panicked");
+        let mut broken = load_email("push");
+        broken.subject = broken.subject.replace("#42", "#99999999999999999999");
+        let ids = [
+            profile.dlq.push("test", "1", load_email("push"), &err),
+            profile
+                .dlq
+                .push("test", "2", load_email("issue_comment"), &err),
+            profile.dlq.push("test", "3", broken, &err),
+        ]
+        .map(Result::unwrap);
+        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+        retry(
+            &config,
+            &profile.github,
+            &mut profile.outputs,
+            &profile.dlq,
+            &ids,
+        )
+        .unwrap();
+
+        // The comment is already delivered so only the push is sent.
+        assert_eq!(sent_kinds(&sink), ["Pushed"]);
+        let seen = &profile.outputs.seen.state;
+        assert!(seen.is_emailed(&issue(), digest::now()));
+
+        // The broken one is kept with the new error.
+        let entries = profile.dlq.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].cursor, "3");
+        assert!(entries[0].errors[0].contains("invalid issue number"));
+    }
+
+    #[test]
+    fn keep_cursor_on_api_failure() {
+        let config = Config::for_test(testing::temp_dir("api-failure"), "");
+        let sink = FakeSink::default();
+
+        // The API rejects the token. The email is not a dead letter since it may be parsed later.
+        let broken = FakeGitHub::spawn_unauthorized();
+        let github = broken.client("https://github.com");
+        let mut profile = profile(&config, github, vec![load_email("push")], &sink);
+        assert!(profile.poll().is_err());
+
+        let (_, store) = &profile.sources[0];
+        assert_eq!(store.state.cursor, None);
+        assert!(profile.dlq.list().unwrap().is_empty());
+        assert!(sink.log().sent.is_empty());
+    }
 }
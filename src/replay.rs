@@ -0,0 +1,50 @@
+use crate::{config::Config, email, github, notif, rules, sink};
+use anyhow::Result;
+use std::path::Path;
+
+pub fn run(config: &Config, mailbox_path: &Path, send_notifs: bool) -> Result<()> {
+    let github = github::Client::new(
+        github::Credentials {
+            auth_token: config.github.auth_token.clone(),
+        },
+        config.github.endpoints.clone(),
+    )?;
+
+    let emails = email::read_raw_messages(mailbox_path)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, raw)| match email::parse_message(&raw) {
+            Ok(email) => Some(email),
+            Err(err) => {
+                log::warn!("failed to parse message #{}: {}", idx, err);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    log::info!("{} emails found", emails.len());
+
+    let ctx = notif::BuildContext { github: &github };
+    let notifs = notif::build_notifications(ctx, emails)?;
+
+    if !send_notifs {
+        println!("{}", serde_json::to_string_pretty(&notifs)?);
+        return Ok(());
+    }
+
+    let mut sinks = config
+        .sinks
+        .iter()
+        .map(|s| sink::build(*s, config))
+        .collect::<Result<Vec<_>>>()?;
+    for notif in notifs {
+        let decision = config.rules.decide(&notif);
+        if decision.delivery == rules::Delivery::Drop {
+            continue;
+        }
+        for sink in sinks.iter_mut() {
+            sink.send(&notif, &decision)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use crate::{
+    github,
+    notif::{NotifKind, Notification},
+};
+use anyhow::Result;
+use regex::Regex;
+
+#[derive(Debug)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    defaults: Vec<Rule>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub matcher: Matcher,
+    pub action: Action,
+}
+
+#[derive(Debug, Default)]
+pub struct Matcher {
+    pub kind: Option<NotifKind>,
+    pub repo: Option<Glob>,
+    pub actor: Option<Glob>,
+    pub team: Option<Glob>,
+    pub review_state: Option<github::ReviewState>,
+    pub comment: Option<Regex>,
+    pub reason: Option<github::NotificationReason>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Alert,
+    Silence,
+    Drop,
+    Unsubscribe,
+    Digest,
+    Route { channel: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Alert,
+    Silence,
+    Drop,
+    Unsubscribe,
+    Digest,
+}
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub delivery: Delivery,
+    pub channel: Option<String>,
+}
+
+/// A simple glob pattern in which `*` matches any characters and `?` matches a single character.
+#[derive(Debug)]
+pub struct Glob {
+    re: Regex,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut re = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => re.push_str(".*"),
+                '?' => re.push('.'),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        Ok(Glob {
+            re: Regex::new(&re)?,
+        })
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.re.is_match(value)
+    }
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>, digest_kinds: &[NotifKind]) -> Self {
+        RuleSet {
+            rules,
+            defaults: default_rules(digest_kinds),
+        }
+    }
+
+    // Rules are evaluated in order: user-defined rules first, then the default ones.
+    // The first matching rule decides the delivery, and the first matching `Route` rule
+    // decides the destination channel. So routing can be combined with any delivery.
+    pub fn decide(&self, notif: &Notification) -> Decision {
+        let mut delivery = None;
+        let mut channel = None;
+        for rule in self.rules.iter().chain(self.defaults.iter()) {
+            if !rule.matcher.is_match(notif) {
+                continue;
+            }
+            match rule.action.delivery() {
+                Some(d) => {
+                    delivery.get_or_insert(d);
+                }
+                None => {
+                    if let Action::Route { channel: c } = &rule.action {
+                        channel.get_or_insert_with(|| c.clone());
+                    }
+                }
+            }
+            if delivery.is_some() && channel.is_some() {
+                break;
+            }
+        }
+        Decision {
+            // The default rules end with a catch-all rule so this fallback is just in case.
+            delivery: delivery.unwrap_or(Delivery::Alert),
+            channel,
+        }
+    }
+}
+
+impl Action {
+    fn delivery(&self) -> Option<Delivery> {
+        match self {
+            Action::Alert => Some(Delivery::Alert),
+            Action::Silence => Some(Delivery::Silence),
+            Action::Drop => Some(Delivery::Drop),
+            Action::Unsubscribe => Some(Delivery::Unsubscribe),
+            Action::Digest => Some(Delivery::Digest),
+            Action::Route { .. } => None,
+        }
+    }
+}
+
+impl Matcher {
+    pub fn is_match(&self, notif: &Notification) -> bool {
+        let detail = &notif.detail;
+        if let Some(kind) = self.kind {
+            if kind != detail.kind() {
+                return false;
+            }
+        }
+        if let Some(repo) = &self.repo {
+            match detail.repo_fullname() {
+                Some(name) if repo.is_match(&name) => {}
+                _ => return false,
+            }
+        }
+        if let Some(actor) = &self.actor {
+            match detail.actor() {
+                Some(user) if actor.is_match(&user.login) => {}
+                _ => return false,
+            }
+        }
+        if let Some(team) = &self.team {
+            match detail.team() {
+                Some(name) if team.is_match(name) => {}
+                _ => return false,
+            }
+        }
+        if let Some(state) = &self.review_state {
+            match detail.review_state() {
+                Some(s) if s == state => {}
+                _ => return false,
+            }
+        }
+        if let Some(comment) = &self.comment {
+            match detail.comment() {
+                Some(text) if comment.is_match(text) => {}
+                _ => return false,
+            }
+        }
+        if self.reason.is_some() && self.reason != notif.reason {
+            return false;
+        }
+        true
+    }
+}
+
+// The default rules reproduce the behavior before the rules were configurable,
+// except that the notifications mentioning the user are always alerted.
+// The digested kinds are inserted after the mention rule so that mentions are not digested.
+fn default_rules(digest_kinds: &[NotifKind]) -> Vec<Rule> {
+    let kind_rule = |kind: NotifKind, action: Action| Rule {
+        matcher: Matcher {
+            kind: Some(kind),
+            ..Matcher::default()
+        },
+        action,
+    };
+    let mut rules = vec![
+        kind_rule(NotifKind::TeamReviewRequested, Action::Unsubscribe),
+        Rule {
+            matcher: Matcher {
+                reason: Some(github::NotificationReason::Mention),
+                ..Matcher::default()
+            },
+            action: Action::Alert,
+        },
+    ];
+    rules.extend(
+        digest_kinds
+            .iter()
+            .map(|kind| kind_rule(*kind, Action::Digest)),
+    );
+    rules.extend(vec![
+        kind_rule(NotifKind::Pushed, Action::Silence),
+        kind_rule(NotifKind::PrOpened, Action::Silence),
+        kind_rule(NotifKind::IssueClosed, Action::Silence),
+        kind_rule(NotifKind::WorkflowCancelled, Action::Silence),
+        Rule {
+            matcher: Matcher::default(),
+            action: Action::Alert,
+        },
+    ]);
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notif::NotifDetail;
+
+    fn push(reason: github::NotificationReason) -> Notification {
+        Notification {
+            id: None,
+            detail: NotifDetail::Pushed {
+                pr: github::IssueInfo {
+                    repo: github::Repository {
+                        owner: String::from("acme"),
+                        name: String::from("widgets"),
+                    },
+                    number: 42,
+                    title: String::from("Add widgets"),
+                },
+                diff_url: String::from("https://github.com/acme/widgets/pull/42/files"),
+                committer: github::User {
+                    login: String::from("alice"),
+                    avatar_url: String::new(),
+                },
+                commits: Vec::new(),
+                status: None,
+            },
+            reason: Some(reason),
+        }
+    }
+
+    #[test]
+    fn digest_quiet_kinds_except_mentions() {
+        let rules = RuleSet::new(Vec::new(), &[NotifKind::Pushed]);
+        let subscribed = rules.decide(&push(github::NotificationReason::Subscribed));
+        let mentioned = rules.decide(&push(github::NotificationReason::Mention));
+        assert_eq!(subscribed.delivery, Delivery::Digest);
+        assert_eq!(mentioned.delivery, Delivery::Alert);
+    }
+}
@@ -0,0 +1,107 @@
+//! IDs of the delivered notifications, so that a notification is never delivered twice
+//! when a batch is retried or the same email comes from multiple sources.
+
+use crate::{config::Config, github, source};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+};
+
+// Several days of notifications. The sources never go back this far even after a long downtime,
+// so an ID older than these is never delivered again.
+const MAX_SEEN: usize = 5000;
+
+// The GitHub API emits a thread after the grace period from its update, and the email of
+// the update usually arrives before that. Twice the period also covers the poll interval.
+const EMAILED_ISSUE_SECS: u64 = 2 * source::GRACE_SECS;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeenState {
+    pub ids: VecDeque<String>,
+    // The issues notified by emails recently, keyed by `owner/name#number` with the time.
+    // Their notifications from the GitHub API are skipped as duplicates.
+    #[serde(default)]
+    pub emailed_issues: HashMap<String, u64>,
+    // The sinks which delivered a notification while another sink failed, keyed by its ID.
+    // A retry sends the notification only to the rest.
+    #[serde(default)]
+    pub partially_sent: HashMap<String, Vec<String>>,
+}
+
+impl SeenState {
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.iter().any(|i| i == id)
+    }
+
+    pub fn insert(&mut self, id: String) {
+        self.partially_sent.remove(&id);
+        if self.contains(&id) {
+            return;
+        }
+        self.ids.push_back(id);
+        if self.ids.len() > MAX_SEEN {
+            self.ids.pop_front();
+        }
+    }
+
+    pub fn is_emailed(&self, issue: &github::IssueInfo, now: u64) -> bool {
+        let key = issue_key(issue);
+        self.emailed_issues
+            .get(&key)
+            .is_some_and(|at| now < at + EMAILED_ISSUE_SECS)
+    }
+
+    pub fn mark_emailed(&mut self, issue: &github::IssueInfo, now: u64) {
+        self.emailed_issues
+            .retain(|_, at| now < *at + EMAILED_ISSUE_SECS);
+        self.emailed_issues.insert(issue_key(issue), now);
+    }
+}
+
+fn issue_key(issue: &github::IssueInfo) -> String {
+    format!("{}#{}", issue.repo.fullname(), issue.number)
+}
+
+pub fn store_path(config: &Config) -> Result<PathBuf> {
+    config.state_path("seen.json", &format!(".seen-{}.json", config.profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forget_oldest_ids() {
+        let mut state = SeenState::default();
+        for n in 0..(MAX_SEEN + 1) {
+            state.insert(n.to_string());
+        }
+        state.insert(String::from("1"));
+        assert_eq!(state.ids.len(), MAX_SEEN);
+        assert!(!state.contains("0"));
+        assert!(state.contains("1"));
+        assert!(state.contains(&MAX_SEEN.to_string()));
+    }
+
+    #[test]
+    fn forget_emailed_issues_after_window() {
+        let issue = |number| github::IssueInfo {
+            repo: github::Repository {
+                owner: String::from("acme"),
+                name: String::from("widgets"),
+            },
+            number,
+            title: String::new(),
+        };
+        let mut state = SeenState::default();
+        state.mark_emailed(&issue(42), 100);
+        assert!(state.is_emailed(&issue(42), 100 + EMAILED_ISSUE_SECS - 1));
+        assert!(!state.is_emailed(&issue(42), 100 + EMAILED_ISSUE_SECS));
+        assert!(!state.is_emailed(&issue(43), 100));
+
+        state.mark_emailed(&issue(43), 100 + EMAILED_ISSUE_SECS);
+        assert_eq!(state.emailed_issues.len(), 1);
+    }
+}
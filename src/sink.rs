@@ -0,0 +1,35 @@
+#[cfg(test)]
+pub mod fake;
+mod slack;
+mod stdout;
+
+pub use self::slack::SlackSink;
+pub use stdout::StdoutSink;
+
+use crate::{
+    config::{Config, SinkConfig},
+    digest::DigestItem,
+    notif::Notification,
+    rules::Decision,
+};
+use anyhow::Result;
+
+/// A destination of notifications.
+pub trait Sink {
+    // A name to identify the sink in stored states.
+    fn name(&self) -> &'static str;
+
+    fn send(&mut self, notif: &Notification, decision: &Decision) -> Result<()>;
+
+    // Send queued notifications at once. A digest may take several messages. `sent` counts
+    // the messages already delivered so that a retry after a failure resumes from there.
+    fn send_digest(&mut self, items: &[DigestItem], sent: &mut usize) -> Result<()>;
+}
+
+pub fn build<'a>(sink: SinkConfig, config: &'a Config) -> Result<Box<dyn Sink + 'a>> {
+    let sink: Box<dyn Sink + 'a> = match sink {
+        SinkConfig::Slack => Box::new(SlackSink::new(config)?),
+        SinkConfig::Stdout => Box::new(StdoutSink),
+    };
+    Ok(sink)
+}
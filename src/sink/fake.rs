@@ -0,0 +1,80 @@
+//! An in-memory sink for tests. The clones of a sink share the same log, so a test can keep
+//! one to inspect what the boxed one received.
+
+use crate::{digest::DigestItem, notif::Notification, rules::Decision, sink::Sink};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::{
+    cell::{Ref, RefCell},
+    rc::Rc,
+};
+
+#[derive(Debug, Clone)]
+pub struct FakeSink {
+    name: &'static str,
+    log: Rc<RefCell<SinkLog>>,
+}
+
+impl Default for FakeSink {
+    fn default() -> Self {
+        FakeSink::named("fake")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SinkLog {
+    // The delivered notifications in JSON.
+    pub sent: Vec<Value>,
+    pub digests: Vec<Vec<Value>>,
+    // Fail every delivery while it is down.
+    pub down: bool,
+}
+
+impl FakeSink {
+    pub fn named(name: &'static str) -> Self {
+        FakeSink {
+            name,
+            log: Rc::default(),
+        }
+    }
+
+    pub fn log(&self) -> Ref<'_, SinkLog> {
+        self.log.borrow()
+    }
+
+    pub fn set_down(&self, down: bool) {
+        self.log.borrow_mut().down = down;
+    }
+}
+
+impl Sink for FakeSink {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn send(&mut self, notif: &Notification, _decision: &Decision) -> Result<()> {
+        let mut log = self.log.borrow_mut();
+        if log.down {
+            return Err(anyhow!("fake sink is down"));
+        }
+        log.sent.push(serde_json::to_value(notif)?);
+        Ok(())
+    }
+
+    fn send_digest(&mut self, items: &[DigestItem], sent: &mut usize) -> Result<()> {
+        let mut log = self.log.borrow_mut();
+        if *sent > 0 {
+            return Ok(());
+        }
+        if log.down {
+            return Err(anyhow!("fake sink is down"));
+        }
+        let notifs = items
+            .iter()
+            .map(|item| serde_json::to_value(&item.notif))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        log.digests.push(notifs);
+        *sent = 1;
+        Ok(())
+    }
+}
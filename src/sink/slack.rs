@@ -0,0 +1,80 @@
+use crate::{
+    config::Config, digest::DigestItem, notif::Notification, notifier, rules::Decision, sink::Sink,
+    slack, store::Store, threads::ThreadState,
+};
+use anyhow::Result;
+
+/// Posts notifications to Slack channels.
+#[derive(Debug)]
+pub struct SlackSink<'a> {
+    client: slack::Client,
+    config: &'a Config,
+    threads: Option<Store<ThreadState>>,
+}
+
+impl<'a> SlackSink<'a> {
+    pub fn new(config: &'a Config) -> Result<Self> {
+        let client = slack::Client::new(
+            slack::Credentials {
+                bot_token: config.slack.bot_token.clone(),
+            },
+            &config.slack.api_base,
+        )?;
+        let threads = if config.slack.thread_replies {
+            let path =
+                config.state_path("threads.json", &format!(".threads-{}.json", config.profile))?;
+            Some(Store::load(path, || Ok(ThreadState::default()))?)
+        } else {
+            None
+        };
+        Ok(SlackSink {
+            client,
+            config,
+            threads,
+        })
+    }
+}
+
+impl Sink for SlackSink<'_> {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    fn send(&mut self, notif: &Notification, decision: &Decision) -> Result<()> {
+        notifier::notify_by_slack(
+            &self.client,
+            self.config,
+            decision,
+            notif,
+            self.threads.as_mut(),
+        )?;
+        Ok(())
+    }
+
+    fn send_digest(&mut self, items: &[DigestItem], sent: &mut usize) -> Result<()> {
+        // Post a digest message per destination channel.
+        let mut channels: Vec<(&str, Vec<&Notification>)> = Vec::new();
+        for item in items {
+            let channel = match &item.channel {
+                Some(channel) => channel.as_str(),
+                None => {
+                    let repo_fullname = item.notif.detail.repo_fullname();
+                    self.config.slack.dest_channel_for(repo_fullname.as_deref())
+                }
+            };
+            match channels.iter_mut().find(|(c, _)| *c == channel) {
+                Some((_, notifs)) => notifs.push(&item.notif),
+                None => channels.push((channel, vec![&item.notif])),
+            }
+        }
+        let messages = channels.iter().flat_map(|(channel, notifs)| {
+            let texts = notifier::digest_messages(self.config, notifs);
+            texts.into_iter().map(move |text| (*channel, text))
+        });
+        for (channel, text) in messages.skip(*sent) {
+            notifier::notify_digest_by_slack(&self.client, channel, &text)?;
+            *sent += 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{digest::DigestItem, notif::Notification, rules::Decision, sink::Sink};
+use anyhow::Result;
+
+/// Prints notifications as JSON lines. This is handy to check rules and parsers.
+#[derive(Debug)]
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn send(&mut self, notif: &Notification, _decision: &Decision) -> Result<()> {
+        println!("{}", serde_json::to_string(notif)?);
+        Ok(())
+    }
+
+    fn send_digest(&mut self, items: &[DigestItem], sent: &mut usize) -> Result<()> {
+        if *sent > 0 {
+            return Ok(());
+        }
+        let notifs = items.iter().map(|item| &item.notif).collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string(&serde_json::json!({ "digest": notifs }))?
+        );
+        *sent = 1;
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ mod api;
 
 pub use api::Client;
 
-use crate::email::Email;
+use crate::email::{self, Email};
 use serde::{Deserialize, Serialize};
 use std::mem;
 
@@ -23,17 +23,34 @@ pub struct ConvHistoryParams<'a> {
     pub channel: &'a str,
     pub oldest_ts: &'a str,
     pub limit: Option<&'a str>,
+    // The `next_cursor` of the previous page.
+    pub cursor: Option<&'a str>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ChatMessage<'a> {
     pub channel: &'a str,
+    // The text is used as a fallback of the blocks (e.g. in notifications) if they exist.
     pub text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<&'a serde_json::Value>,
     pub username: Option<&'a str>,
     pub icon_url: Option<&'a str>,
     pub icon_emoji: Option<&'a str>,
     pub unfurl_links: bool,
     pub unfurl_media: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<&'a str>,
+    // Also show a thread reply in the channel.
+    pub reply_broadcast: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseMessage<'a> {
+    pub text: &'a str,
+    // "ephemeral" shows the message only to the user who interacted.
+    pub response_type: &'a str,
+    pub replace_original: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -42,25 +59,29 @@ pub struct EmailAddress {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(tag = "pretty_type")]
 pub enum File {
+    #[default]
     Unknown,
     Email {
         subject: String,
         to: Vec<EmailAddress>,
         from: Vec<EmailAddress>,
         plain_text: String,
+        #[serde(default)]
+        headers: EmailHeaders,
     },
 }
 
-impl Default for File {
-    fn default() -> Self {
-        Self::Unknown
-    }
+// Some of the headers of an email file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct EmailHeaders {
+    pub message_id: Option<String>,
 }
 
 pub fn extract_email_from_message(msg: Message) -> Option<Email> {
+    let ts = msg.ts;
     match msg.files {
         Some(mut files) if !files.is_empty() => {
             let file = mem::take(&mut files[0]);
@@ -69,10 +90,17 @@ pub fn extract_email_from_message(msg: Message) -> Option<Email> {
                     mut from,
                     subject,
                     plain_text,
+                    headers,
                     ..
                 } => {
                     let sender_name = mem::take(&mut from[0].name);
+                    let id = headers
+                        .message_id
+                        .as_deref()
+                        .and_then(email::id_from_message_id)
+                        .unwrap_or_else(|| format!("slack:{}", ts));
                     Some(Email {
+                        id: Some(id),
                         subject,
                         sender_name,
                         text_body: plain_text,
@@ -84,3 +112,39 @@ pub fn extract_email_from_message(msg: Message) -> Option<Email> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn email_message(headers: serde_json::Value) -> Message {
+        serde_json::from_value(json!({
+            "ts": "1622541600.000100",
+            "text": "",
+            "files": [{
+                "pretty_type": "Email",
+                "subject": "[acme/widgets] Add widgets (#42)",
+                "to": [],
+                "from": [{"address": "notifications@github.com", "name": "alice"}],
+                "plain_text": "Hi",
+                "headers": headers,
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn use_message_id_as_email_id() {
+        let msg = email_message(json!({ "message_id": "<acme/widgets/pull/42@github.com>" }));
+        let email = extract_email_from_message(msg).unwrap();
+        // The same ID as the email read from IMAP.
+        assert_eq!(
+            email.id.as_deref(),
+            Some("email:acme/widgets/pull/42@github.com")
+        );
+
+        let email = extract_email_from_message(email_message(json!({}))).unwrap();
+        assert_eq!(email.id.as_deref(), Some("slack:1622541600.000100"));
+    }
+}
@@ -1,35 +1,62 @@
 use crate::{
-    slack::{ChatMessage, ConvHistoryParams, Credentials, Message},
-    web,
+    slack::{ChatMessage, ConvHistoryParams, Credentials, Message, ResponseMessage},
+    web::{
+        self,
+        retry::{self, RetryPolicy, RetryableError},
+    },
 };
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use reqwest::blocking::RequestBuilder;
+use serde::{de::DeserializeOwned, Deserialize};
 
 #[derive(Debug)]
 pub struct Client {
     client: reqwest::blocking::Client,
+    api_base: String,
+    retry: RetryPolicy,
 }
 
 #[derive(Debug, Deserialize)]
 struct RawConvHistoryResponse {
     pub error: Option<String>,
     pub messages: Option<Vec<Message>>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub response_metadata: Option<ResponseMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseMetadata {
+    pub next_cursor: String,
 }
 
 #[derive(Debug)]
 pub struct ConvHistoryResponse {
     pub messages: Vec<Message>,
+    // The cursor to fetch the next (older) page, if any.
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct RawChatPostMsgResponse {
     pub error: Option<String>,
+    pub ts: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ChatPostMsgResponse {
+    // The timestamp of the posted message, which identifies it in the channel.
+    pub ts: String,
 }
 
 impl Client {
-    pub fn new(creds: Credentials) -> Result<Self> {
+    pub fn new(creds: Credentials, api_base: &str) -> Result<Self> {
         let client = Self::build_inner_client(creds)?;
-        Ok(Client { client })
+        Ok(Client {
+            client,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            retry: RetryPolicy::default(),
+        })
     }
 
     fn build_inner_client(creds: Credentials) -> Result<reqwest::blocking::Client> {
@@ -48,24 +75,35 @@ impl Client {
     }
 
     pub fn conversations_history(&self, params: ConvHistoryParams) -> Result<ConvHistoryResponse> {
-        let url = "https://slack.com/api/conversations.history";
+        let url = format!("{}/conversations.history", self.api_base);
 
         let mut query_params = vec![("channel", params.channel), ("oldest", params.oldest_ts)];
         if let Some(limit) = params.limit {
             query_params.push(("limit", limit));
         }
-
-        // Note that the conversations.history endpoint returns max 100 messages by default.
-        let res = self.client.get(url).query(&query_params).send()?;
-
-        if res.status().as_u16() != 200 {
-            web::log_error_response(&url, res);
-            return Err(anyhow!("failed to fetch conversations history"));
+        if let Some(cursor) = params.cursor {
+            query_params.push(("cursor", cursor));
         }
 
-        let data = res.json::<RawConvHistoryResponse>()?;
+        // Note that the conversations.history endpoint returns max 100 messages by default
+        // and the rest must be fetched page by page from newest to oldest.
+        let req = self.client.get(&url).query(&query_params);
+        let data: RawConvHistoryResponse = self.call(
+            req,
+            &url,
+            "failed to fetch conversations history",
+            retry::is_retryable,
+        )?;
+        let has_more = data.has_more;
+        let next_cursor = data
+            .response_metadata
+            .map(|m| m.next_cursor)
+            .filter(|cursor| has_more && !cursor.is_empty());
         match data.messages {
-            Some(messages) => Ok(ConvHistoryResponse { messages }),
+            Some(messages) => Ok(ConvHistoryResponse {
+                messages,
+                next_cursor,
+            }),
             None => {
                 let err_msg = data.error.unwrap_or_else(|| String::from("unknown error"));
                 Err(anyhow!("failed to fetch conversation history: {}", err_msg))
@@ -73,26 +111,76 @@ impl Client {
         }
     }
 
-    pub fn chat_post_message(&self, msg: &ChatMessage) -> Result<()> {
-        let url = "https://slack.com/api/chat.postMessage";
+    pub fn chat_post_message(&self, msg: &ChatMessage) -> Result<ChatPostMsgResponse> {
+        let url = format!("{}/chat.postMessage", self.api_base);
         let body = serde_json::ser::to_string(&msg)?;
-        let res = self
+        let req = self
             .client
-            .post(url)
+            .post(&url)
             .header("Content-Type", "application/json")
-            .body(body)
-            .send()?;
-
-        if res.status().as_u16() != 200 {
-            web::log_error_response(&url, res);
-            return Err(anyhow!("failed to post chat message"));
+            .body(body);
+        // Posting is not idempotent, so retry only when Slack rejected the request by rate limits.
+        // A timeout or a server error may have posted the message already.
+        let data: RawChatPostMsgResponse = self.call(
+            req,
+            &url,
+            "failed to post chat message",
+            retry::is_rate_limited,
+        )?;
+        match data.ts {
+            Some(ts) => Ok(ChatPostMsgResponse { ts }),
+            None => {
+                let err_msg = data.error.unwrap_or_else(|| String::from("unknown error"));
+                Err(anyhow!("failed to post chat message: {}", err_msg))
+            }
         }
+    }
 
-        let data = res.json::<RawChatPostMsgResponse>()?;
-        if let Some(err_msg) = data.error {
-            return Err(anyhow!("failed to post chat message: {}", err_msg));
-        }
+    // Call a Web API method with retries. Slack tells rate limits by the `ratelimited` error
+    // in some cases as well as HTTP 429.
+    fn call<T: DeserializeOwned>(
+        &self,
+        req: RequestBuilder,
+        url: &str,
+        failure: &str,
+        should_retry: fn(&anyhow::Error) -> bool,
+    ) -> Result<T> {
+        self.retry.run_if(should_retry, || {
+            let req = req
+                .try_clone()
+                .ok_or_else(|| anyhow!("request cannot be retried"))?;
+            let res = retry::check_status(req.send()?)?;
+            if res.status().as_u16() != 200 {
+                web::log_error_response(url, res);
+                return Err(anyhow!("{}", failure));
+            }
+            let data = res.json::<serde_json::Value>()?;
+            if data["error"] == "ratelimited" {
+                return Err(RetryableError {
+                    message: format!("rate limited: {}", url),
+                    retry_after: None,
+                    rate_limited: true,
+                }
+                .into());
+            }
+            Ok(serde_json::from_value(data)?)
+        })
+    }
 
+    // Reply to an interaction via its response URL. Like posting a message,
+    // this is retried only on rate limits so that the reply is not posted twice.
+    pub fn respond(&self, response_url: &str, msg: &ResponseMessage) -> Result<()> {
+        let req = self.client.post(response_url).json(msg);
+        let res = self.retry.run_if(retry::is_rate_limited, || {
+            let req = req
+                .try_clone()
+                .ok_or_else(|| anyhow!("request cannot be retried"))?;
+            retry::check_status(req.send()?)
+        })?;
+        if res.status().as_u16() != 200 {
+            web::log_error_response(response_url, res);
+            return Err(anyhow!("failed to respond to interaction"));
+        }
         Ok(())
     }
 }
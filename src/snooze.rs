@@ -0,0 +1,43 @@
+//! Issues snoozed locally. Their notifications are dropped until the snooze expires.
+
+use crate::{config::Config, github::IssueInfo};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnoozeState {
+    pub snoozes: Vec<Snooze>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snooze {
+    pub repo: String,
+    pub number: usize,
+    // UNIX time in seconds.
+    pub until: u64,
+}
+
+// The snoozes are written by the interactivity server and read by the polling loop.
+pub fn store_path(config: &Config) -> Result<PathBuf> {
+    config.state_path("snoozes.json", &format!(".snoozes-{}.json", config.profile))
+}
+
+impl SnoozeState {
+    pub fn is_snoozed(&self, issue: &IssueInfo, now: u64) -> bool {
+        let repo = issue.repo.fullname();
+        self.snoozes
+            .iter()
+            .any(|s| s.repo == repo && s.number == issue.number && now < s.until)
+    }
+
+    pub fn snooze(&mut self, repo: &str, number: usize, until: u64, now: u64) {
+        self.snoozes
+            .retain(|s| now < s.until && !(s.repo == repo && s.number == number));
+        self.snoozes.push(Snooze {
+            repo: repo.to_string(),
+            number,
+            until,
+        });
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+pub mod fake;
+mod github_notifications;
+mod imap;
+mod slack_channel;
+
+pub use self::imap::ImapSource;
+pub use github_notifications::{GitHubNotificationsSource, GRACE_SECS};
+pub use slack_channel::SlackChannelSource;
+
+use crate::{
+    config::{GitHubConfig, SlackConfig, SourceConfig},
+    email::Email,
+    notif::Notification,
+};
+use anyhow::Result;
+
+/// A source of GitHub notifications.
+pub trait Source {
+    /// A unique identifier of the source used to name its state file.
+    fn id(&self) -> String;
+
+    /// Fetches messages after the given cursor, sorted from oldest to newest.
+    /// The cursor is opaque for callers and `None` means starting from now.
+    /// Fetching from the cursor of a message must not return the message again.
+    fn fetch(&mut self, cursor: Option<&str>) -> Result<Batch>;
+
+    /// Called after the fetched batch is processed successfully.
+    fn ack(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Batch {
+    pub messages: Vec<Message>,
+    // The cursor after the batch, which may be ahead of the last message
+    // since a source can skip irrelevant messages.
+    pub cursor: String,
+}
+
+impl Batch {
+    pub fn empty(cursor: String) -> Self {
+        Batch {
+            messages: Vec::new(),
+            cursor,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Message {
+    // The cursor to resume after this message, so that the progress can be saved per message.
+    pub cursor: String,
+    pub content: Content,
+}
+
+#[derive(Debug)]
+pub enum Content {
+    Email(Email),
+    // A notification built without an email, such as one from the GitHub API.
+    Notif(Notification),
+}
+
+pub fn build(
+    config: &SourceConfig,
+    slack_config: &SlackConfig,
+    github_config: &GitHubConfig,
+) -> Result<Box<dyn Source>> {
+    let source: Box<dyn Source> = match config {
+        SourceConfig::SlackChannel { channel_id } => {
+            Box::new(SlackChannelSource::new(slack_config, channel_id.clone())?)
+        }
+        SourceConfig::Imap(imap_config) => Box::new(ImapSource::new(imap_config)),
+        SourceConfig::GitHubNotifications => {
+            Box::new(GitHubNotificationsSource::new(github_config)?)
+        }
+    };
+    Ok(source)
+}
@@ -0,0 +1,52 @@
+//! An in-memory source for tests. It serves the given emails with their 1-based positions
+//! as cursors, and `None` cursor means the beginning unlike the real sources.
+
+use crate::{
+    email::Email,
+    source::{Batch, Content, Message, Source},
+};
+use anyhow::{anyhow, Result};
+
+#[derive(Debug)]
+pub struct FakeSource {
+    id: String,
+    emails: Vec<Email>,
+}
+
+impl FakeSource {
+    pub fn new(id: &str, emails: Vec<Email>) -> Self {
+        FakeSource {
+            id: id.to_string(),
+            emails,
+        }
+    }
+}
+
+impl Source for FakeSource {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn fetch(&mut self, cursor: Option<&str>) -> Result<Batch> {
+        let start = match cursor {
+            Some(cursor) => cursor
+                .parse::<usize>()
+                .map_err(|_| anyhow!("invalid fake cursor: {}", cursor))?,
+            None => 0,
+        };
+        let messages = self
+            .emails
+            .iter()
+            .enumerate()
+            .skip(start)
+            .map(|(idx, email)| Message {
+                cursor: (idx + 1).to_string(),
+                content: Content::Email(email.clone()),
+            })
+            .collect();
+        Ok(Batch {
+            messages,
+            cursor: self.emails.len().max(start).to_string(),
+        })
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    config::GitHubConfig,
+    digest::now,
+    github,
+    notif::{NotifDetail, Notification},
+    source::{Batch, Content, Message, Source},
+};
+use anyhow::Result;
+use std::convert::TryFrom;
+
+// Threads are emitted after this grace period so that the notifications from emails,
+// which usually arrive earlier, can be used to skip the duplicated ones.
+pub const GRACE_SECS: u64 = 10 * 60;
+
+// GitHub tells the poll interval by `X-Poll-Interval`, which is usually 60 seconds.
+const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+/// Polls the GitHub Notifications API to complement the email sources.
+/// It can catch the notifications whose emails are dropped on the way.
+#[derive(Debug)]
+pub struct GitHubNotificationsSource {
+    client: github::Client,
+    last_modified: Option<String>,
+    next_poll_at: u64,
+    // Whether some threads are waiting for the grace period.
+    // We cannot use `If-Modified-Since` then because they would not be listed again.
+    has_pending: bool,
+    // The threads to be marked as read after forwarding.
+    emitted: Vec<String>,
+}
+
+impl GitHubNotificationsSource {
+    pub fn new(config: &GitHubConfig) -> Result<Self> {
+        let client = github::Client::new(
+            github::Credentials {
+                auth_token: config.auth_token.clone(),
+            },
+            config.endpoints.clone(),
+        )?;
+        Ok(GitHubNotificationsSource {
+            client,
+            last_modified: None,
+            next_poll_at: 0,
+            has_pending: false,
+            emitted: Vec::new(),
+        })
+    }
+
+    fn build_notif(&self, thread: &github::NotificationThread) -> Result<Notification> {
+        let repo = github::Repository {
+            owner: thread.repository.owner.login.clone(),
+            name: thread.repository.name.clone(),
+        };
+        // Unknown reasons such as `security_alert` are just ignored.
+        let reason = serde_json::from_value(serde_json::Value::from(thread.reason.as_str())).ok();
+        let number = thread
+            .subject
+            .url
+            .as_deref()
+            .and_then(|url| url.rsplit('/').next())
+            .and_then(|n| n.parse().ok());
+
+        let path = match thread.subject.kind.as_str() {
+            "PullRequest" => Some("pull"),
+            "Issue" => Some("issues"),
+            _ => None,
+        };
+        let detail = match (path, number) {
+            (Some(path), Some(number)) => {
+                let url = format!(
+                    "{}/{}/{}/{}",
+                    self.client.web_base(),
+                    repo.fullname(),
+                    path,
+                    number
+                );
+                let issue = github::IssueInfo {
+                    repo,
+                    number,
+                    title: thread.subject.title.clone(),
+                };
+                self.latest_comment(thread, &issue, &url)?
+                    .unwrap_or(NotifDetail::ThreadUpdated { url, issue })
+            }
+            _ => NotifDetail::Unknown {
+                sender: String::from("GitHub"),
+                body: vec![format!("[{}] {}", repo.fullname(), thread.subject.title)],
+            },
+        };
+        // The same comment may be notified by emails, so use its anchor if any.
+        let id = detail
+            .url()
+            .and_then(github::anchor_id)
+            .unwrap_or_else(|| format!("thread:{}:{}", thread.id, thread.updated_at));
+        Ok(Notification {
+            id: Some(id),
+            detail,
+            reason,
+        })
+    }
+
+    // Show the content of the latest comment if it is an issue comment.
+    fn latest_comment(
+        &self,
+        thread: &github::NotificationThread,
+        issue: &github::IssueInfo,
+        url: &str,
+    ) -> Result<Option<NotifDetail>> {
+        let comment_id = thread
+            .subject
+            .latest_comment_url
+            .as_deref()
+            .and_then(|url| url.split_once("/issues/comments/"))
+            .and_then(|(_, id)| id.parse().ok());
+        let comment_id = match comment_id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let comment = self
+            .client
+            .get_issue_comment(&github::GetIssueCommentParams {
+                repo: &issue.repo,
+                comment_id,
+            })?;
+        Ok(comment.map(|comment| NotifDetail::Commented {
+            url: format!("{}#issuecomment-{}", url, comment_id),
+            issue: issue.clone(),
+            commenter: comment.user,
+            comment: comment.body,
+        }))
+    }
+}
+
+impl Source for GitHubNotificationsSource {
+    fn id(&self) -> String {
+        String::from("github-notifications")
+    }
+
+    // The cursor is the update time of the last emitted thread in ISO 8601.
+    fn fetch(&mut self, cursor: Option<&str>) -> Result<Batch> {
+        let now = now();
+        let since = match cursor {
+            Some(since) => since.to_string(),
+            None => format_iso8601(now),
+        };
+        if now < self.next_poll_at {
+            return Ok(Batch::empty(since));
+        }
+
+        let res = self
+            .client
+            .list_notifications(&github::ListNotificationsParams {
+                since: &since,
+                if_modified_since: match self.has_pending {
+                    true => None,
+                    false => self.last_modified.as_deref(),
+                },
+            })?;
+        self.next_poll_at = now + res.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+        if res.last_modified.is_some() {
+            self.last_modified = res.last_modified;
+        }
+
+        let mut threads = match res.threads {
+            Some(threads) => threads
+                .into_iter()
+                .filter_map(|t| match parse_iso8601(&t.updated_at) {
+                    Some(updated_at) => Some((updated_at, t)),
+                    None => {
+                        log::warn!("invalid updated_at of thread {}: {}", t.id, t.updated_at);
+                        None
+                    }
+                })
+                .collect::<Vec<_>>(),
+            None => return Ok(Batch::empty(since)),
+        };
+        threads.sort_by_key(|(updated_at, _)| *updated_at);
+
+        let mut cursor = since;
+        let mut messages = Vec::new();
+        self.has_pending = false;
+        for (updated_at, thread) in threads {
+            if now < updated_at + GRACE_SECS {
+                self.has_pending = true;
+                break;
+            }
+            messages.push(Message {
+                cursor: thread.updated_at.clone(),
+                content: Content::Notif(self.build_notif(&thread)?),
+            });
+            self.emitted.push(thread.id);
+            cursor = thread.updated_at;
+        }
+
+        Ok(Batch { messages, cursor })
+    }
+
+    fn ack(&mut self) -> Result<()> {
+        for thread_id in self.emitted.drain(..) {
+            self.client
+                .mark_thread_read(&github::MarkThreadReadParams {
+                    thread_id: &thread_id,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+// Parse a UTC time such as `2021-06-01T10:30:00Z` into UNIX time.
+fn parse_iso8601(time: &str) -> Option<u64> {
+    let (date, time) = time.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|s| s.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|s| s.parse::<u64>().ok());
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+    let days = u64::try_from(days_from_civil(y, m, d)).ok()?;
+    Some(days * 86400 + hh * 3600 + mm * 60 + ss)
+}
+
+fn format_iso8601(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y,
+        m,
+        d,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// The algorithms from http://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn convert_iso8601() {
+        assert_eq!(parse_iso8601("2021-06-01T10:30:00Z"), Some(1_622_543_400));
+        assert_eq!(format_iso8601(1_622_543_400), "2021-06-01T10:30:00Z");
+        assert_eq!(parse_iso8601("2021-06-01"), None);
+    }
+
+    #[test]
+    fn fetch_threads_after_grace_period() {
+        let fake = testing::fake_github();
+        let mut source = GitHubNotificationsSource {
+            client: fake.client("https://github.com"),
+            last_modified: None,
+            next_poll_at: 0,
+            has_pending: false,
+            emitted: Vec::new(),
+        };
+
+        let batch = source.fetch(Some("2021-06-01T00:00:00Z")).unwrap();
+        let notifs = batch
+            .messages
+            .iter()
+            .filter_map(|msg| match &msg.content {
+                Content::Notif(notif) => Some(notif),
+                Content::Email(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let details = notifs.iter().map(|n| &n.detail).collect::<Vec<_>>();
+        match details[..] {
+            [NotifDetail::Commented {
+                url,
+                issue,
+                commenter,
+                ..
+            }, NotifDetail::ThreadUpdated {
+                url: url2,
+                issue: issue2,
+            }] => {
+                assert_eq!(
+                    url,
+                    "https://github.com/acme/widgets/pull/42#issuecomment-4004"
+                );
+                assert_eq!(issue.number, 42);
+                assert_eq!(commenter.login, "carol");
+                assert_eq!(issue2.number, 43);
+                assert_eq!(url2, "https://github.com/acme/widgets/issues/43");
+            }
+            _ => panic!("unexpected notifications: {:?}", details),
+        }
+        assert_eq!(notifs[0].reason, Some(github::NotificationReason::Mention));
+        // The thread updated within the grace period is left for the next fetch.
+        assert_eq!(batch.messages[0].cursor, "2021-06-01T10:30:00Z");
+        assert_eq!(batch.cursor, "2021-06-02T09:00:00Z");
+        assert!(source.has_pending);
+        assert_eq!(source.emitted, ["1", "2"]);
+
+        source.ack().unwrap();
+        assert!(source.emitted.is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    config::ImapConfig,
+    imap,
+    source::{Batch, Content, Message, Source},
+};
+use anyhow::{Context, Result};
+
+/// Reads emails from a folder of an IMAP mailbox. The cursor is the last processed UID
+/// prefixed with the UIDVALIDITY of the folder, such as `1700000000:42`.
+#[derive(Debug)]
+pub struct ImapSource {
+    client: imap::Client,
+    username: String,
+    folder: String,
+}
+
+impl ImapSource {
+    pub fn new(config: &ImapConfig) -> Self {
+        let client = imap::Client::new(
+            imap::Server {
+                host: config.host.clone(),
+                port: config.port,
+                tls: config.tls,
+            },
+            imap::Credentials {
+                username: config.username.clone(),
+                password: config.password.clone(),
+            },
+        );
+        ImapSource {
+            client,
+            username: config.username.clone(),
+            folder: config.folder.clone(),
+        }
+    }
+}
+
+impl Source for ImapSource {
+    fn id(&self) -> String {
+        // UIDs are unique only within a folder.
+        format!("imap-{}-{}", self.username, self.folder.replace('/', "_"))
+    }
+
+    fn fetch(&mut self, cursor: Option<&str>) -> Result<Batch> {
+        let (uid_validity, last_uid) = match cursor {
+            Some(cursor) => parse_cursor(cursor).context("invalid IMAP cursor")?,
+            None => (None, None),
+        };
+        let mut data = self.client.fetch_unseen(imap::FetchParams {
+            folder: &self.folder,
+            last_uid,
+            uid_validity,
+        })?;
+        let uid_validity = data.uid_validity;
+        data.messages.sort_by_key(|msg| msg.uid);
+        let messages = data
+            .messages
+            .into_iter()
+            .filter_map(|msg| {
+                let uid = msg.uid;
+                imap::extract_email_from_message(msg).map(|email| Message {
+                    cursor: format_cursor(uid_validity, uid),
+                    content: Content::Email(email),
+                })
+            })
+            .collect();
+        Ok(Batch {
+            messages,
+            cursor: format_cursor(uid_validity, data.last_uid),
+        })
+    }
+}
+
+// A cursor saved by older versions has only the UID.
+fn parse_cursor(cursor: &str) -> Result<(Option<u32>, Option<u32>)> {
+    match cursor.split_once(':') {
+        Some((validity, uid)) => Ok((Some(validity.parse()?), Some(uid.parse()?))),
+        None => Ok((None, Some(cursor.parse()?))),
+    }
+}
+
+fn format_cursor(uid_validity: Option<u32>, uid: u32) -> String {
+    match uid_validity {
+        Some(validity) => format!("{}:{}", validity, uid),
+        None => uid.to_string(),
+    }
+}
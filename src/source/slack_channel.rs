@@ -0,0 +1,247 @@
+use crate::{
+    config::SlackConfig,
+    email::Email,
+    notif::{NotifDetail, Notification},
+    slack,
+    source::{Batch, Content, Message, Source},
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The max number of messages per page, which Slack recommends.
+const PAGE_SIZE: &str = "200";
+
+/// Reads emails forwarded to a Slack channel by the Slack email app.
+#[derive(Debug)]
+pub struct SlackChannelSource {
+    client: slack::Client,
+    channel_id: String,
+    catch_up_limit: usize,
+}
+
+impl SlackChannelSource {
+    pub fn new(config: &SlackConfig, channel_id: String) -> Result<Self> {
+        let client = slack::Client::new(
+            slack::Credentials {
+                bot_token: config.bot_token.clone(),
+            },
+            &config.api_base,
+        )?;
+        Ok(SlackChannelSource {
+            client,
+            channel_id,
+            catch_up_limit: config.catch_up_limit,
+        })
+    }
+}
+
+impl Source for SlackChannelSource {
+    fn id(&self) -> String {
+        self.channel_id.clone()
+    }
+
+    fn fetch(&mut self, cursor: Option<&str>) -> Result<Batch> {
+        let oldest_ts = match cursor {
+            Some(ts) => ts.to_string(),
+            None => current_ts()?,
+        };
+
+        // Walk all the pages so that a backlog after a downtime is not skipped.
+        // The messages are sorted by newest to oldest in and across pages.
+        let mut all_messages = Vec::new();
+        let mut page_cursor = None;
+        let mut pages = 0;
+        loop {
+            pages += 1;
+            let data = self
+                .client
+                .conversations_history(slack::ConvHistoryParams {
+                    channel: &self.channel_id,
+                    // - We don't need to specify the valid ts; it is just a timestamp.
+                    // - The result does not contain a message with the specified ts if exists.
+                    oldest_ts: &oldest_ts,
+                    limit: Some(PAGE_SIZE),
+                    cursor: page_cursor.as_deref(),
+                })?;
+            all_messages.extend(data.messages);
+            match data.next_cursor {
+                Some(next) => page_cursor = Some(next),
+                None => break,
+            }
+        }
+        if pages > 1 {
+            log::info!(
+                "Catching up {} messages in {}",
+                all_messages.len(),
+                self.channel_id
+            );
+        }
+        // Process them from oldest.
+        all_messages.reverse();
+
+        let cursor = match all_messages.last() {
+            Some(msg) => msg.ts.clone(),
+            None => oldest_ts,
+        };
+        let mut emails = all_messages
+            .into_iter()
+            .filter_map(|msg| {
+                let ts = msg.ts.clone();
+                slack::extract_email_from_message(msg).map(|email| (ts, email))
+            })
+            .collect::<Vec<_>>();
+
+        let mut messages = Vec::new();
+        if emails.len() > self.catch_up_limit {
+            let skipped = emails
+                .drain(..(emails.len() - self.catch_up_limit))
+                .collect::<Vec<_>>();
+            log::warn!(
+                "Skip {} emails in {} beyond the catch-up limit",
+                skipped.len(),
+                self.channel_id
+            );
+            messages.push(summarize_skipped(
+                &self.channel_id,
+                self.catch_up_limit,
+                skipped,
+            ));
+        }
+        messages.extend(emails.into_iter().map(|(ts, email)| Message {
+            cursor: ts,
+            content: Content::Email(email),
+        }));
+        Ok(Batch { messages, cursor })
+    }
+}
+
+// Build a notification which tells how many emails were skipped per repository,
+// so that the user can check them in the mail channel.
+fn summarize_skipped(channel_id: &str, limit: usize, skipped: Vec<(String, Email)>) -> Message {
+    let mut counts = BTreeMap::new();
+    for (_, email) in &skipped {
+        let repo = repo_of_subject(&email.subject).unwrap_or("others");
+        *counts.entry(repo.to_string()).or_insert(0) += 1;
+    }
+    let mut body = vec![format!(
+        "Skipped {} older emails in <#{}> since the backlog exceeds the catch-up limit ({}).",
+        skipped.len(),
+        channel_id,
+        limit
+    )];
+    body.extend(
+        counts
+            .into_iter()
+            .map(|(repo, count)| format!("- {}: {}", repo, count)),
+    );
+
+    let first_ts = skipped.first().map_or("", |(ts, _)| ts.as_str());
+    let last_ts = skipped.last().map_or("", |(ts, _)| ts.as_str());
+    Message {
+        cursor: last_ts.to_string(),
+        content: Content::Notif(Notification {
+            id: Some(format!("slack-skipped:{}:{}", first_ts, last_ts)),
+            detail: NotifDetail::Unknown {
+                sender: String::from("Gharry"),
+                body,
+            },
+            reason: None,
+        }),
+    }
+}
+
+// Get the repository from a subject such as "Re: [ryym/gharry] Fix typo (#1234)".
+fn repo_of_subject(subject: &str) -> Option<&str> {
+    let subject = subject.strip_prefix("Re: ").unwrap_or(subject);
+    let (repo, _) = subject.strip_prefix('[')?.split_once(']')?;
+    Some(repo)
+}
+
+fn current_ts() -> Result<String> {
+    let now = SystemTime::now();
+    let current_ts = now
+        .duration_since(UNIX_EPOCH)
+        .context("failed to get current timestamp")?
+        .as_secs();
+    Ok(current_ts.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::server::{self, Response};
+    use serde_json::json;
+    use std::{net::TcpListener, thread};
+
+    fn email_message(ts: u32, repo: &str) -> serde_json::Value {
+        json!({
+            "ts": ts.to_string(),
+            "text": "",
+            "files": [{
+                "pretty_type": "Email",
+                "subject": format!("[{}] Fix typo (#1)", repo),
+                "to": [],
+                "from": [{"address": "notifications@github.com", "name": "alice"}],
+                "plain_text": "Hi",
+            }],
+        })
+    }
+
+    #[test]
+    fn walk_all_pages_and_summarize_beyond_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            server::serve(listener, |req| {
+                let body = if req.path.contains("cursor=page2") {
+                    json!({
+                        "ok": true,
+                        "messages": [email_message(3, "acme/a"), email_message(2, "acme/b"), email_message(1, "acme/a")],
+                        "has_more": false,
+                    })
+                } else {
+                    json!({
+                        "ok": true,
+                        "messages": [email_message(5, "acme/a"), email_message(4, "acme/a")],
+                        "has_more": true,
+                        "response_metadata": {"next_cursor": "page2"},
+                    })
+                };
+                Response {
+                    status: 200,
+                    body: body.to_string(),
+                }
+            })
+        });
+
+        let mut source = SlackChannelSource {
+            client: slack::Client::new(
+                slack::Credentials {
+                    bot_token: String::from("xoxb"),
+                },
+                &api_base,
+            )
+            .unwrap(),
+            channel_id: String::from("C-mail"),
+            catch_up_limit: 3,
+        };
+        let batch = source.fetch(Some("0")).unwrap();
+        assert_eq!(batch.cursor, "5");
+
+        let cursors = batch.messages.iter().map(|m| m.cursor.as_str());
+        assert_eq!(cursors.collect::<Vec<_>>(), ["2", "3", "4", "5"]);
+        match &batch.messages[0].content {
+            Content::Notif(Notification {
+                detail: NotifDetail::Unknown { body, .. },
+                ..
+            }) => assert_eq!(
+                body[1..],
+                [String::from("- acme/a: 1"), String::from("- acme/b: 1")]
+            ),
+            _ => panic!("no summary of skipped emails"),
+        }
+    }
+}
@@ -1,27 +1,15 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     fmt, fs,
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct State {
-    pub last_ts: String,
-}
-
-impl State {
-    pub fn new() -> Result<State> {
-        let now = SystemTime::now();
-        let current_ts = now
-            .duration_since(UNIX_EPOCH)
-            .context("failed to get current timestamp")?
-            .as_secs();
-        Ok(State {
-            last_ts: current_ts.to_string(),
-        })
-    }
+    // An opaque cursor of a source. `last_ts` is its old name when only Slack was supported.
+    #[serde(alias = "last_ts")]
+    pub cursor: Option<String>,
 }
 
 impl fmt::Display for State {
@@ -31,29 +19,29 @@ impl fmt::Display for State {
 }
 
 #[derive(Debug)]
-pub struct Store {
+pub struct Store<S = State> {
     path: PathBuf,
-    pub state: State,
+    pub state: S,
 }
 
-impl Store {
-    pub fn load<F: FnOnce() -> Result<State>>(path: PathBuf, make_state: F) -> Result<Store> {
+impl<S: Serialize + DeserializeOwned> Store<S> {
+    pub fn load<F: FnOnce() -> Result<S>>(path: PathBuf, make_state: F) -> Result<Self> {
         match Store::load_state(&path)? {
             Some(state) => Ok(Store { path, state }),
             None => Store::create(path, make_state()?),
         }
     }
 
-    fn create(path: PathBuf, state: State) -> Result<Store> {
+    fn create(path: PathBuf, state: S) -> Result<Self> {
         Store::store_state(&path, &state)?;
         Ok(Store { path, state })
     }
 
-    fn load_state(path: &Path) -> Result<Option<State>> {
+    fn load_state(path: &Path) -> Result<Option<S>> {
         if !path.exists() {
             return Ok(None);
         }
-        let content = fs::read(&path).context("failed to load state")?;
+        let content = fs::read(path).context("failed to load state")?;
         let state = serde_json::from_slice(&content).with_context(|| {
             format!(
                 "failed to serialize stored state: {}",
@@ -63,15 +51,22 @@ impl Store {
         Ok(Some(state))
     }
 
-    fn store_state(path: &Path, state: &State) -> Result<()> {
+    // Write to a temporary file and rename it so that readers never see a partially written state.
+    fn store_state(path: &Path, state: &S) -> Result<()> {
         let json = serde_json::to_string(state)?;
-        fs::write(path, &json).context("failed to store state")?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, &json).context("failed to store state")?;
+        fs::rename(&tmp_path, path).context("failed to store state")?;
         Ok(())
     }
 
-    pub fn update_state(&mut self, state: State) -> Result<()> {
-        Store::store_state(&self.path, &state)?;
-        self.state = state;
-        Ok(())
+    pub fn update_state_with<F: FnOnce(&mut S)>(&mut self, f: F) -> Result<()> {
+        f(&mut self.state);
+        self.save()
+    }
+
+    // Write the state changed directly through `state`.
+    pub fn save(&self) -> Result<()> {
+        Store::store_state(&self.path, &self.state)
     }
 }
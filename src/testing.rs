@@ -0,0 +1,27 @@
+//! Helpers shared by tests.
+
+use crate::github::fake::FakeGitHub;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// The path of a file or directory under `tests/fixtures`.
+pub fn fixtures_dir(path: impl AsRef<Path>) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path)
+}
+
+// An empty directory for the files of a test. The name must be unique among tests.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gharry-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A fake GitHub API serving `tests/fixtures/github`.
+pub fn fake_github() -> FakeGitHub {
+    FakeGitHub::spawn(fixtures_dir("github"))
+}
@@ -0,0 +1,82 @@
+//! Slack threads of issues, so that notifications of the same issue or PR
+//! are posted as replies to the first one.
+
+use crate::github::IssueInfo;
+use serde::{Deserialize, Serialize};
+
+// Old threads are forgotten so that the state does not grow forever.
+const MAX_THREADS: usize = 1000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ThreadState {
+    pub threads: Vec<Thread>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Thread {
+    pub channel: String,
+    pub repo: String,
+    pub number: usize,
+    pub ts: String,
+}
+
+impl ThreadState {
+    pub fn find(&self, channel: &str, issue: &IssueInfo) -> Option<&str> {
+        let repo = issue.repo.fullname();
+        self.threads
+            .iter()
+            .find(|t| t.channel == channel && t.repo == repo && t.number == issue.number)
+            .map(|t| t.ts.as_str())
+    }
+
+    pub fn insert(&mut self, channel: &str, issue: &IssueInfo, ts: String) {
+        self.threads.push(Thread {
+            channel: channel.to_string(),
+            repo: issue.repo.fullname(),
+            number: issue.number,
+            ts,
+        });
+        if self.threads.len() > MAX_THREADS {
+            let overflow = self.threads.len() - MAX_THREADS;
+            self.threads.drain(..overflow);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Repository;
+
+    fn issue(number: usize) -> IssueInfo {
+        IssueInfo {
+            repo: Repository {
+                owner: String::from("acme"),
+                name: String::from("widgets"),
+            },
+            number,
+            title: String::from("Add widgets"),
+        }
+    }
+
+    #[test]
+    fn find_thread_per_channel_and_issue() {
+        let mut state = ThreadState::default();
+        state.insert("C1", &issue(1), String::from("100.1"));
+        state.insert("C2", &issue(1), String::from("200.1"));
+        assert_eq!(state.find("C1", &issue(1)), Some("100.1"));
+        assert_eq!(state.find("C2", &issue(1)), Some("200.1"));
+        assert_eq!(state.find("C1", &issue(2)), None);
+    }
+
+    #[test]
+    fn forget_oldest_threads() {
+        let mut state = ThreadState::default();
+        for n in 0..(MAX_THREADS + 1) {
+            state.insert("C1", &issue(n), n.to_string());
+        }
+        assert_eq!(state.threads.len(), MAX_THREADS);
+        assert_eq!(state.find("C1", &issue(0)), None);
+        assert_eq!(state.find("C1", &issue(MAX_THREADS)), Some("1000"));
+    }
+}
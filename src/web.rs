@@ -1,3 +1,6 @@
+pub mod retry;
+pub mod server;
+
 use reqwest::blocking::Response;
 
 pub fn log_error_response(url: &str, res: Response) {
@@ -0,0 +1,427 @@
+//! Retries of API requests shared by the GitHub and Slack clients.
+//! Rate limits, server errors and network failures are retried with the delay told by the server
+//! or an exponential backoff. The other errors are fatal and returned immediately, as well as
+//! the ones the server asks to wait longer than the max delay for.
+
+use anyhow::{anyhow, Result};
+use reqwest::{
+    blocking::{RequestBuilder, Response},
+    header::HeaderMap,
+    StatusCode,
+};
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// GitHub asks to wait at least one minute on secondary rate limits without `Retry-After`.
+// See https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api.
+const SECONDARY_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+/// An error worth retrying later, such as a rate limit or a server error.
+#[derive(Debug)]
+pub struct RetryableError {
+    pub message: String,
+    // How long the server asked us to wait, if told.
+    pub retry_after: Option<Duration>,
+    // Whether the server rejected the request without processing it.
+    pub rate_limited: bool,
+}
+
+impl fmt::Display for RetryableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RetryableError {}
+
+/// Whether an error is temporary, so that the caller can retry the whole operation later.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<RetryableError>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|err| !err.is_decode() && !err.is_builder())
+    })
+}
+
+/// Whether an error is a rate limit, which is safe to retry even for a non-idempotent request.
+pub fn is_rate_limited(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<RetryableError>())
+        .any(|err| err.rate_limited)
+}
+
+/// How long the server asked to wait before retrying, if told.
+pub fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<RetryableError>())
+        .and_then(|err| err.retry_after)
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Run an attempt until it succeeds, fails fatally or runs out of retries.
+    pub fn run<T>(&self, attempt: impl FnMut() -> Result<T>) -> Result<T> {
+        self.run_if(is_retryable, attempt)
+    }
+
+    // Run an attempt, retrying only the errors accepted by `should_retry`.
+    // If the server asks to wait longer than the max delay, the error is returned
+    // so that the caller gives up the whole operation for a while.
+    pub fn run_if<T>(
+        &self,
+        should_retry: fn(&anyhow::Error) -> bool,
+        mut attempt: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut backoff = Backoff::new(self.base_delay, self.max_delay);
+        let mut retries = 0;
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(err) if retries < self.max_retries && should_retry(&err) => {
+                    let delay = match retry_after(&err) {
+                        Some(delay) if delay > self.max_delay => return Err(err),
+                        Some(delay) => delay,
+                        None => backoff.next_delay(),
+                    };
+                    retries += 1;
+                    log::warn!(
+                        "request failed, retry #{} after {:?}: {:#}",
+                        retries,
+                        delay,
+                        err
+                    );
+                    thread::sleep(delay);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Send a request with retries. A response of a fatal error status such as 404 is returned
+    // as is so that the caller can handle it.
+    pub fn send(&self, req: RequestBuilder) -> Result<Response> {
+        self.run(|| {
+            let req = req
+                .try_clone()
+                .ok_or_else(|| anyhow!("request cannot be retried"))?;
+            check_status(req.send()?)
+        })
+    }
+}
+
+// Turn rate limits and server errors into retryable errors.
+pub fn check_status(res: Response) -> Result<Response> {
+    let status = res.status();
+    let url = res.url().to_string();
+    let delay = server_delay(res.headers(), now_secs());
+    let retryable = |delay, rate_limited| RetryableError {
+        message: format!("{} {}", url, status),
+        retry_after: delay,
+        rate_limited,
+    };
+
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Err(retryable(delay, true).into()),
+        StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => Err(retryable(delay, false).into()),
+        // GitHub responds 403 to both the primary and secondary rate limits.
+        StatusCode::FORBIDDEN if delay.is_some() => Err(retryable(delay, true).into()),
+        StatusCode::FORBIDDEN => {
+            let body = res.text().unwrap_or_default();
+            if body.contains("secondary rate limit") {
+                Err(retryable(Some(SECONDARY_RATE_LIMIT_DELAY), true).into())
+            } else {
+                log::warn!("request failed: {}, response body: {}", url, body);
+                Err(anyhow!("request forbidden: {}", url))
+            }
+        }
+        _ => Ok(res),
+    }
+}
+
+// Get the delay told by `Retry-After` or the GitHub's rate limit headers.
+fn server_delay(headers: &HeaderMap, now: u64) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(value) = header("Retry-After").map(str::trim) {
+        // `Retry-After` is either seconds or an HTTP date.
+        let secs = match value.parse() {
+            Ok(secs) => Some(secs),
+            Err(_) => parse_http_date(value).map(|date| date.saturating_sub(now)),
+        };
+        if let Some(secs) = secs {
+            return Some(Duration::from_secs(secs));
+        }
+    }
+    if header("X-RateLimit-Remaining") == Some("0") {
+        let reset = header("X-RateLimit-Reset").and_then(|v| v.parse::<u64>().ok())?;
+        // Wait one more second since the reset time is truncated.
+        return Some(Duration::from_secs(reset.saturating_sub(now) + 1));
+    }
+    None
+}
+
+// Parse an HTTP date such as "Wed, 21 Oct 2015 07:28:00 GMT" into UNIX seconds.
+// Only the IMF-fixdate format is supported, which the servers must send (RFC 9110).
+fn parse_http_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    let (day, month, year, time) = match parts[..] {
+        [_, day, month, year, time, "GMT"] => (day, month, year, time),
+        _ => return None,
+    };
+    let day = day.parse::<u64>().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let year = year.parse::<u64>().ok()?;
+    let mut hms = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    if year < 1970 || day == 0 || day > 31 || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+
+    // Count the days since 1970-01-01 in the civil calendar, starting years from March.
+    let (y, mp) = if month > 2 {
+        (year, month - 3)
+    } else {
+        (year - 1, month + 9)
+    };
+    let era_days = (y / 400) * 146_097;
+    let yoe = y % 400;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era_days + doe).checked_sub(719_468)?;
+    Some(days * 86_400 + h * 3_600 + m * 60 + s)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// An exponential backoff with jitter. Each delay is randomly chosen
+/// between the half and the whole of the doubled one, up to the max.
+#[derive(Debug)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max,
+            attempts: 0,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let exp = self
+            .base
+            .checked_mul(2u32.saturating_pow(self.attempts))
+            .map_or(self.max, |d| d.min(self.max));
+        self.attempts += 1;
+        let half = exp / 2;
+        half + half.mul_f64(random_fraction())
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+// A random number in [0, 1) without an extra dependency, using the random keys of `RandomState`.
+fn random_fraction() -> f64 {
+    let n = RandomState::new().build_hasher().finish();
+    (n >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::server::{self, Response};
+    use reqwest::header::HeaderValue;
+    use std::{
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    fn spawn_server(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        thread::spawn(move || {
+            server::serve(listener, |_| {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses.get(n).copied().unwrap_or(200);
+                Response::error(status, "")
+            })
+        });
+        (url, count)
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn retry_server_errors_and_rate_limits() {
+        let (url, count) = spawn_server(vec![503, 429]);
+        let client = reqwest::blocking::Client::new();
+        let res = fast_policy().send(client.get(&url)).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn return_fatal_errors_immediately() {
+        let (url, count) = spawn_server(vec![404]);
+        let client = reqwest::blocking::Client::new();
+        let res = fast_policy().send(client.get(&url)).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let (url, _) = spawn_server(vec![500, 500, 500, 500]);
+        let err = fast_policy().send(client.get(&url)).unwrap_err();
+        assert!(is_retryable(&err));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn give_up_on_too_long_delay() {
+        let mut attempts = 0;
+        let err = fast_policy()
+            .run(|| -> Result<()> {
+                attempts += 1;
+                Err(RetryableError {
+                    message: String::from("rate limited"),
+                    retry_after: Some(Duration::from_secs(3600)),
+                    rate_limited: true,
+                }
+                .into())
+            })
+            .unwrap_err();
+        assert_eq!(attempts, 1);
+        assert_eq!(retry_after(&err), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn retry_only_accepted_errors() {
+        let (url, count) = spawn_server(vec![503, 503]);
+        let client = reqwest::blocking::Client::new();
+        let send = || check_status(client.post(&url).send()?);
+        let err = fast_policy().run_if(is_rate_limited, send).unwrap_err();
+        assert!(is_retryable(&err));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (url, count) = spawn_server(vec![429]);
+        let send = || check_status(client.post(&url).send()?);
+        let res = fast_policy().run_if(is_rate_limited, send).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn get_delay_from_headers() {
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_str(value).unwrap());
+            }
+            headers
+        };
+        let now = 1_000;
+        assert_eq!(server_delay(&headers(&[]), now), None);
+        assert_eq!(
+            server_delay(&headers(&[("Retry-After", "30")]), now),
+            Some(Duration::from_secs(30))
+        );
+        let now = 1_445_412_400;
+        assert_eq!(
+            server_delay(
+                &headers(&[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")]),
+                now
+            ),
+            Some(Duration::from_secs(80))
+        );
+        assert_eq!(
+            server_delay(
+                &headers(&[("Retry-After", "Tue, 20 Oct 2015 07:28:00 GMT")]),
+                now
+            ),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            server_delay(&headers(&[("Retry-After", "soon")]), now),
+            None
+        );
+        let now = 1_000;
+        assert_eq!(
+            server_delay(
+                &headers(&[
+                    ("X-RateLimit-Remaining", "0"),
+                    ("X-RateLimit-Reset", "1100")
+                ]),
+                now
+            ),
+            Some(Duration::from_secs(101))
+        );
+        assert_eq!(
+            server_delay(
+                &headers(&[
+                    ("X-RateLimit-Remaining", "10"),
+                    ("X-RateLimit-Reset", "1100")
+                ]),
+                now
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays = (0..5).map(|_| backoff.next_delay()).collect::<Vec<_>>();
+        let bounds = [1, 2, 4, 5, 5].map(Duration::from_secs);
+        for (delay, bound) in delays.iter().zip(bounds) {
+            assert!(*delay >= bound / 2 && *delay <= bound, "{:?}", delays);
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,239 @@
+//! A minimal HTTP server to receive callbacks such as Slack interactions and GitHub webhooks.
+//! It handles one request per connection, which is enough for occasional callbacks.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+// Callbacks such as Slack interactions are small. A server expecting larger bodies
+// raises the limit by `serve_with_limit`.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+// The request line and headers are small for callbacks. Larger ones are rejected
+// so that a client cannot make the server buffer lines endlessly.
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+const MAX_HEADERS: usize = 100;
+
+// A client sending nothing or trickling bytes would block the other requests
+// since they are handled one by one, so the whole request must arrive in this time.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // The path without the query string.
+    pub fn route(&self) -> &str {
+        self.path.split('?').next().unwrap_or("")
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response {
+            status: 200,
+            body: String::new(),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Response {
+            status,
+            body: message.to_string(),
+        }
+    }
+}
+
+pub fn serve<F: FnMut(&Request) -> Response>(listener: TcpListener, handle: F) {
+    serve_with_limit(listener, MAX_BODY_SIZE, handle)
+}
+
+pub fn serve_with_limit<F: FnMut(&Request) -> Response>(
+    listener: TcpListener,
+    max_body_size: usize,
+    mut handle: F,
+) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        let res = match read_request(&stream, deadline, max_body_size) {
+            Ok(req) => handle(&req),
+            Err(err) => {
+                log::warn!("failed to read request: {:#}", err);
+                Response::error(400, "bad request")
+            }
+        };
+        if let Err(err) = write_response(&mut stream, &res) {
+            log::warn!("failed to write response: {}", err);
+        }
+    }
+}
+
+fn read_request(stream: &TcpStream, deadline: Instant, max_body_size: usize) -> Result<Request> {
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline });
+    let mut head = (&mut reader).take(MAX_HEADER_BYTES);
+    let request_line = read_head_line(&mut head)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(anyhow!("invalid request line: {}", request_line.trim())),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_head_line(&mut head)?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(anyhow!("too many headers"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let content_len = headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+        .map(|(_, v)| v.parse::<usize>())
+        .transpose()
+        .context("invalid Content-Length")?
+        .unwrap_or(0);
+    if content_len > max_body_size {
+        return Err(anyhow!("too large body: {} bytes", content_len));
+    }
+    let mut body = vec![0; content_len];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+// Read a line of the request line or headers, which must end within the header limit.
+fn read_head_line<R: BufRead>(head: &mut io::Take<R>) -> Result<String> {
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return match head.limit() {
+            0 => Err(anyhow!("too large headers")),
+            _ => Err(anyhow!("connection closed before the end of headers")),
+        };
+    }
+    Ok(line)
+}
+
+// Fails to read once the deadline passes, however slowly the client sends data.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out"));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+fn write_response(stream: &mut TcpStream, res: &Response) -> Result<()> {
+    let reason = match res.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        res.status,
+        reason,
+        res.body.len(),
+        res.body
+    );
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Read a request sent by a client which keeps the connection open.
+    fn read(request: &str, timeout: Duration) -> Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        read_request(&stream, Instant::now() + timeout, MAX_BODY_SIZE)
+    }
+
+    #[test]
+    fn read_small_request() {
+        let req = read(
+            "POST /path HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi",
+            REQUEST_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(req.path, "/path");
+        assert_eq!(req.header("content-length"), Some("2"));
+        assert_eq!(req.body, b"hi");
+    }
+
+    #[test]
+    fn reject_oversized_header() {
+        let value = "a".repeat(MAX_HEADER_BYTES as usize);
+        let request = format!("POST / HTTP/1.1\r\nX-Large: {}\r\n\r\n", value);
+        let err = read(&request, REQUEST_TIMEOUT).unwrap_err();
+        assert_eq!(err.to_string(), "too large headers");
+    }
+
+    #[test]
+    fn reject_too_many_headers() {
+        let headers = (0..=MAX_HEADERS).map(|i| format!("X-Header-{}: {}\r\n", i, i));
+        let request = format!("GET / HTTP/1.1\r\n{}\r\n", headers.collect::<String>());
+        let err = read(&request, REQUEST_TIMEOUT).unwrap_err();
+        assert_eq!(err.to_string(), "too many headers");
+    }
+
+    #[test]
+    fn give_up_unfinished_request_at_deadline() {
+        let request = "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhi";
+        assert!(read(request, Duration::from_millis(100)).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Receives GitHub webhook deliveries for repositories where we can install hooks.
+//! The notifications are passed to the polling loop so that they go through the same rules and sinks.
+
+mod event;
+
+use crate::{
+    config::{Config, WebhookConfig},
+    github,
+    notif::Notification,
+    web::server::{self, Request, Response},
+};
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+// The path of the Payload URL set in the webhook.
+const PATH: &str = "/";
+
+// GitHub caps webhook payloads at 25 MB. Such a body is accepted only by the webhook server,
+// whose requests are dropped unless signed.
+const MAX_BODY_SIZE: usize = 25 * 1024 * 1024;
+
+/// Notifications received by webhooks, keyed by profile names.
+pub type Deliveries = HashMap<String, Receiver<Notification>>;
+
+/// Notifications received by webhooks but not delivered yet.
+/// They are stored until the sinks accept them, since GitHub does not redeliver them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Inbox {
+    pub notifs: Vec<Notification>,
+}
+
+#[derive(Debug)]
+pub struct Webhooks {
+    profile: String,
+    secret: String,
+    login_name: String,
+    web_base: String,
+    github: github::Client,
+    sender: Sender<Notification>,
+}
+
+// Start a webhook server for each profile that enables it.
+pub fn spawn_servers(configs: &[Config]) -> Result<Deliveries> {
+    let mut deliveries = HashMap::new();
+    for config in configs {
+        let webhook = match &config.webhook {
+            Some(webhook) => webhook,
+            None => continue,
+        };
+        let listener = TcpListener::bind(&webhook.listen)
+            .with_context(|| format!("failed to listen on {}", webhook.listen))?;
+        let (sender, receiver) = mpsc::channel();
+        let mut webhooks = Webhooks::new(config, webhook, sender)?;
+        log::info!(
+            "[{}] Listening GitHub webhooks on {}",
+            config.profile,
+            webhook.listen
+        );
+        thread::spawn(move || {
+            server::serve_with_limit(listener, MAX_BODY_SIZE, |req| webhooks.handle(req))
+        });
+        deliveries.insert(config.profile.clone(), receiver);
+    }
+    Ok(deliveries)
+}
+
+impl Webhooks {
+    pub fn new(
+        config: &Config,
+        webhook: &WebhookConfig,
+        sender: Sender<Notification>,
+    ) -> Result<Self> {
+        let github = github::Client::new(
+            github::Credentials {
+                auth_token: config.github.auth_token.clone(),
+            },
+            config.github.endpoints.clone(),
+        )?;
+        Ok(Webhooks {
+            profile: config.profile.clone(),
+            secret: webhook.secret.clone(),
+            login_name: config.github.login_name.clone(),
+            web_base: config.github.endpoints.web_base.clone(),
+            github,
+            sender,
+        })
+    }
+
+    pub fn handle(&mut self, req: &Request) -> Response {
+        if req.method != "POST" || req.route() != PATH {
+            return Response::error(404, "not found");
+        }
+        if !verify_signature(&self.secret, req) {
+            log::warn!("[{}] Reject webhook with invalid signature", self.profile);
+            return Response::error(401, "invalid signature");
+        }
+        let event = req.header("X-GitHub-Event").unwrap_or("");
+        match self.receive(event, req.header("X-GitHub-Delivery"), &req.body) {
+            Ok(()) => Response::ok(),
+            Err(err) => {
+                log::error!("[{}] Failed to handle webhook: {:#}", self.profile, err);
+                Response::error(500, "failed to handle webhook")
+            }
+        }
+    }
+
+    fn receive(&self, event: &str, delivery_id: Option<&str>, body: &[u8]) -> Result<()> {
+        if event == "ping" {
+            log::info!("[{}] Webhook ping received", self.profile);
+            return Ok(());
+        }
+        let payload: event::Payload =
+            serde_json::from_slice(body).with_context(|| format!("invalid {} payload", event))?;
+        let notif = event::to_notification(
+            event,
+            payload,
+            event::Context {
+                delivery_id,
+                login_name: &self.login_name,
+                web_base: &self.web_base,
+                github: &self.github,
+            },
+        );
+        match notif {
+            Some(notif) => {
+                log::info!("[{}] {} webhook received", self.profile, event);
+                self.sender
+                    .send(notif)
+                    .context("polling loop is not running")?;
+            }
+            None => log::debug!("ignore {} webhook", event),
+        }
+        Ok(())
+    }
+}
+
+// Verify a delivery is sent from GitHub.
+// See https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries.
+fn verify_signature(secret: &str, req: &Request) -> bool {
+    let signature = match req
+        .header("X-Hub-Signature-256")
+        .and_then(|s| s.strip_prefix("sha256="))
+        .map(hex::decode)
+    {
+        Some(Ok(signature)) => signature,
+        _ => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(&req.body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,285 @@
+//! Maps webhook payloads into notifications. Only pushes to PRs need an API call for the commits.
+//! See https://docs.github.com/en/webhooks/webhook-events-and-payloads.
+
+use crate::{
+    github,
+    notif::{NotifDetail, Notification},
+};
+use serde::Deserialize;
+
+// The fields used by the events we handle. Each event has only some of them.
+#[derive(Debug, Deserialize)]
+pub struct Payload {
+    action: Option<String>,
+    sender: github::User,
+    repository: Repository,
+    pull_request: Option<PullRequest>,
+    issue: Option<Issue>,
+    comment: Option<Comment>,
+    review: Option<Review>,
+    requested_reviewer: Option<github::User>,
+    requested_team: Option<Team>,
+    workflow_run: Option<WorkflowRun>,
+    // The head commits before and after a `synchronize` of a PR.
+    before: Option<String>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    name: String,
+    owner: Owner,
+}
+
+#[derive(Debug, Deserialize)]
+struct Owner {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    number: usize,
+    title: String,
+    user: github::User,
+    merged: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    number: usize,
+    title: String,
+    user: github::User,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comment {
+    html_url: String,
+    body: String,
+    user: github::User,
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    html_url: String,
+    body: Option<String>,
+    state: String,
+    user: github::User,
+}
+
+#[derive(Debug, Deserialize)]
+struct Team {
+    slug: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkflowRun {
+    name: String,
+    html_url: String,
+    conclusion: Option<String>,
+}
+
+pub struct Context<'a> {
+    // The `X-GitHub-Delivery` header, which is kept on redeliveries.
+    pub delivery_id: Option<&'a str>,
+    pub login_name: &'a str,
+    pub web_base: &'a str,
+    pub github: &'a github::Client,
+}
+
+// Convert a payload into a notification. Unsupported events and actions are ignored.
+pub fn to_notification(event: &str, payload: Payload, ctx: Context) -> Option<Notification> {
+    // Unlike emails, webhooks are delivered for your own activities too.
+    if payload.sender.login == ctx.login_name {
+        return None;
+    }
+    let repo = github::Repository {
+        owner: payload.repository.owner.login.clone(),
+        name: payload.repository.name.clone(),
+    };
+    let action = payload.action.as_deref().unwrap_or("");
+
+    let (detail, reason) = match (event, action) {
+        ("pull_request", _) => {
+            let pr = payload.pull_request?;
+            let reason = author_reason(&pr.user, ctx.login_name);
+            let info = issue_info(&repo, pr.number, &pr.title);
+            match action {
+                "opened" | "reopened" => {
+                    let detail = NotifDetail::PrOpened {
+                        opener: payload.sender,
+                        pr: info,
+                        // Webhooks are converted without API calls, so PR statuses are unknown.
+                        status: None,
+                    };
+                    (detail, reason)
+                }
+                "closed" => {
+                    let detail = NotifDetail::IssueClosed {
+                        closer: payload.sender,
+                        issue: info,
+                        is_merge: pr.merged.unwrap_or(false),
+                    };
+                    (detail, reason)
+                }
+                "review_requested" => {
+                    let detail = match (payload.requested_reviewer, payload.requested_team) {
+                        (Some(reviewer), _) if reviewer.login == ctx.login_name => {
+                            NotifDetail::DirectReviewRequested {
+                                reviewee: payload.sender,
+                                pr: info,
+                                status: None,
+                            }
+                        }
+                        (_, Some(team)) => NotifDetail::TeamReviewRequested {
+                            reviewee: payload.sender,
+                            pr: info,
+                            team: format!("{}/{}", repo.owner, team.slug),
+                        },
+                        _ => return None,
+                    };
+                    (detail, Some(github::NotificationReason::ReviewRequested))
+                }
+                "synchronize" => {
+                    let (before, after) = (payload.before?, payload.after?);
+                    // The payload has no commits. A push is still notified without them
+                    // if they cannot be fetched, since the diff link tells the change.
+                    let commits = ctx
+                        .github
+                        .compare_commits(&github::CompareCommitsParams {
+                            repo: &repo,
+                            base: &before,
+                            head: &after,
+                        })
+                        .unwrap_or_else(|err| {
+                            log::warn!("failed to fetch pushed commits: {:#}", err);
+                            Vec::new()
+                        });
+                    let diff_url = format!(
+                        "{}/{}/pull/{}/files/{}..{}",
+                        ctx.web_base,
+                        repo.fullname(),
+                        pr.number,
+                        before,
+                        after
+                    );
+                    let detail = NotifDetail::Pushed {
+                        pr: info,
+                        diff_url,
+                        committer: payload.sender,
+                        commits,
+                        status: None,
+                    };
+                    (detail, reason)
+                }
+                _ => return None,
+            }
+        }
+
+        ("pull_request_review", "submitted") => {
+            let pr = payload.pull_request?;
+            let review = payload.review?;
+            let state = serde_json::from_value(review.state.to_uppercase().into()).ok()?;
+            let comment = review.body.unwrap_or_default();
+            let reason = mention_reason(&comment, ctx.login_name)
+                .or_else(|| author_reason(&pr.user, ctx.login_name));
+            let detail = NotifDetail::PrReviewed {
+                url: review.html_url,
+                pr: issue_info(&repo, pr.number, &pr.title),
+                state,
+                commenter: review.user,
+                comment,
+                status: None,
+            };
+            (detail, reason)
+        }
+
+        ("pull_request_review_comment", "created") => {
+            let pr = payload.pull_request?;
+            let comment = payload.comment?;
+            let reason = mention_reason(&comment.body, ctx.login_name)
+                .or_else(|| author_reason(&pr.user, ctx.login_name));
+            let detail = NotifDetail::PrReviewCommented {
+                url: comment.html_url,
+                pr: issue_info(&repo, pr.number, &pr.title),
+                commenter: comment.user,
+                comment: comment.body,
+                status: None,
+            };
+            (detail, reason)
+        }
+
+        ("issue_comment", "created") => {
+            let issue = payload.issue?;
+            let comment = payload.comment?;
+            let reason = mention_reason(&comment.body, ctx.login_name)
+                .or_else(|| author_reason(&issue.user, ctx.login_name));
+            let detail = NotifDetail::Commented {
+                url: comment.html_url,
+                issue: issue_info(&repo, issue.number, &issue.title),
+                commenter: comment.user,
+                comment: comment.body,
+            };
+            (detail, reason)
+        }
+
+        ("issues", "closed") => {
+            let issue = payload.issue?;
+            let reason = author_reason(&issue.user, ctx.login_name);
+            let detail = NotifDetail::IssueClosed {
+                closer: payload.sender,
+                issue: issue_info(&repo, issue.number, &issue.title),
+                is_merge: false,
+            };
+            (detail, reason)
+        }
+
+        ("workflow_run", "completed") => {
+            let run = payload.workflow_run?;
+            if run.conclusion.as_deref() != Some("cancelled") {
+                return None;
+            }
+            let detail = NotifDetail::WorkflowCancelled {
+                sender_name: payload.sender.login,
+                repo_fullname: repo.fullname(),
+                workflow_name: run.name,
+                result_url: run.html_url,
+            };
+            (detail, Some(github::NotificationReason::CiActivity))
+        }
+
+        _ => return None,
+    };
+    // The same comment may be notified by emails, so use its anchor if any.
+    let id = detail.url().and_then(github::anchor_id).or_else(|| {
+        ctx.delivery_id
+            .map(|delivery_id| format!("github-delivery:{}", delivery_id))
+    });
+    Some(Notification { id, detail, reason })
+}
+
+fn issue_info(repo: &github::Repository, number: usize, title: &str) -> github::IssueInfo {
+    github::IssueInfo {
+        repo: repo.clone(),
+        number,
+        title: title.to_string(),
+    }
+}
+
+fn mention_reason(body: &str, login_name: &str) -> Option<github::NotificationReason> {
+    let mention = format!("@{}", login_name);
+    let mentioned = body.match_indices(&mention).any(|(idx, _)| {
+        let next = body[idx + mention.len()..].chars().next();
+        !matches!(next, Some(c) if c.is_alphanumeric() || c == '-')
+    });
+    match mentioned {
+        true => Some(github::NotificationReason::Mention),
+        false => None,
+    }
+}
+
+fn author_reason(author: &github::User, login_name: &str) -> Option<github::NotificationReason> {
+    match author.login == login_name {
+        true => Some(github::NotificationReason::Author),
+        false => None,
+    }
+}
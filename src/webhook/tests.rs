@@ -0,0 +1,200 @@
+use super::*;
+use crate::{github, notif::NotifDetail, testing};
+use serde_json::{json, Value};
+use std::{sync::mpsc::Receiver, time::Duration};
+
+const SECRET: &str = "It's a Secret to Everybody";
+
+struct TestServer {
+    url: String,
+    receiver: Receiver<Notification>,
+}
+
+fn spawn_server() -> TestServer {
+    let (sender, receiver) = mpsc::channel();
+    let mut webhooks = Webhooks {
+        profile: String::from("test"),
+        secret: String::from(SECRET),
+        login_name: String::from("ryym"),
+        web_base: String::from("https://github.com"),
+        github: testing::fake_github().client("https://github.com"),
+        sender,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || server::serve(listener, |req| webhooks.handle(req)));
+    TestServer { url, receiver }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn post(url: &str, event: &str, payload: &Value, signature: Option<&str>) -> u16 {
+    let body = payload.to_string();
+    let signature = signature.map_or_else(|| sign(SECRET, &body), String::from);
+    reqwest::blocking::Client::new()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-GitHub-Event", event)
+        .header("X-Hub-Signature-256", signature)
+        .body(body)
+        .send()
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+fn user(login: &str) -> Value {
+    json!({ "login": login, "avatar_url": format!("https://avatars.example.com/{}", login) })
+}
+
+fn repository() -> Value {
+    json!({ "name": "widgets", "owner": { "login": "acme" } })
+}
+
+fn pull_request(author: &str) -> Value {
+    json!({
+        "number": 42,
+        "title": "Add widgets",
+        "user": user(author),
+        "merged": false,
+    })
+}
+
+#[test]
+fn map_mention_in_issue_comment() {
+    let server = spawn_server();
+    let payload = json!({
+        "action": "created",
+        "sender": user("bob"),
+        "repository": repository(),
+        "issue": { "number": 7, "title": "Widgets break", "user": user("carol") },
+        "comment": {
+            "html_url": "https://github.com/acme/widgets/issues/7#issuecomment-4004",
+            "body": "@ryym could you take a look?",
+            "user": user("bob"),
+        },
+    });
+
+    assert_eq!(post(&server.url, "issue_comment", &payload, None), 200);
+
+    let notif = server
+        .receiver
+        .recv_timeout(Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(notif.reason, Some(github::NotificationReason::Mention));
+    match notif.detail {
+        NotifDetail::Commented { issue, comment, .. } => {
+            assert_eq!(issue.repo.fullname(), "acme/widgets");
+            assert_eq!(issue.number, 7);
+            assert_eq!(comment, "@ryym could you take a look?");
+        }
+        detail => panic!("unexpected notification: {:?}", detail),
+    }
+}
+
+#[test]
+fn map_review_and_push_to_pr() {
+    let server = spawn_server();
+    let opened = json!({
+        "action": "opened",
+        "sender": user("alice"),
+        "repository": repository(),
+        "pull_request": pull_request("alice"),
+    });
+    let review = json!({
+        "action": "submitted",
+        "sender": user("bob"),
+        "repository": repository(),
+        "pull_request": pull_request("alice"),
+        "review": {
+            "html_url": "https://github.com/acme/widgets/pull/42#pullrequestreview-1001",
+            "body": null,
+            "state": "changes_requested",
+            "user": user("bob"),
+        },
+    });
+    let synchronize = json!({
+        "action": "synchronize",
+        "before": "0011223",
+        "after": "89abcde",
+        "sender": user("alice"),
+        "repository": repository(),
+        "pull_request": pull_request("alice"),
+    });
+
+    for (event, payload) in [
+        ("pull_request", opened),
+        ("pull_request_review", review),
+        ("pull_request", synchronize),
+    ] {
+        assert_eq!(post(&server.url, event, &payload, None), 200);
+    }
+
+    let details = (0..3)
+        .map(|_| {
+            let notif = server
+                .receiver
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap();
+            notif.detail
+        })
+        .collect::<Vec<_>>();
+    match &details[..] {
+        [NotifDetail::PrOpened { .. }, NotifDetail::PrReviewed { state, comment, .. }, NotifDetail::Pushed {
+            pr,
+            diff_url,
+            commits,
+            ..
+        }] => {
+            assert_eq!(*state, github::ReviewState::ChangesRequested);
+            assert_eq!(comment, "");
+            assert_eq!(pr.number, 42);
+            assert_eq!(
+                diff_url,
+                "https://github.com/acme/widgets/pull/42/files/0011223..89abcde"
+            );
+            let hashes = commits.iter().map(|c| c.hash.as_str());
+            assert_eq!(hashes.collect::<Vec<_>>(), ["4567890", "89abcde"]);
+        }
+        details => panic!("unexpected notifications: {:?}", details),
+    }
+}
+
+#[test]
+fn ignore_own_activities_and_pushes() {
+    let server = spawn_server();
+    let own_comment = json!({
+        "action": "created",
+        "sender": user("ryym"),
+        "repository": repository(),
+        "issue": { "number": 7, "title": "Widgets break", "user": user("carol") },
+        "comment": { "html_url": "", "body": "I'm on it", "user": user("ryym") },
+    });
+    // Pushes to PRs are notified by `synchronize` actions instead.
+    let push = json!({
+        "ref": "refs/heads/main",
+        "before": "0011223",
+        "after": "89abcde",
+        "sender": user("alice"),
+        "repository": repository(),
+        "commits": [{ "id": "89abcde", "message": "Release" }],
+    });
+
+    assert_eq!(post(&server.url, "issue_comment", &own_comment, None), 200);
+    assert_eq!(post(&server.url, "push", &push, None), 200);
+    assert!(server.receiver.try_recv().is_err());
+}
+
+#[test]
+fn reject_invalid_signature() {
+    let server = spawn_server();
+    let payload = json!({ "zen": "Keep it logically awesome." });
+    let signature = sign("wrong secret", &payload.to_string());
+
+    assert_eq!(post(&server.url, "ping", &payload, Some(&signature)), 401);
+    assert_eq!(post(&server.url, "ping", &payload, None), 200);
+}